@@ -0,0 +1,42 @@
+//! Time per character of `parse_with_options` for inputs which used to make the tokenizer rescan the page.
+//! Run with `cargo bench --bench tokenizer`. The time per character should stay flat as the input grows.
+
+use std::time::{Duration, Instant};
+
+const SIZES: [usize; 4] = [2_000, 4_000, 8_000, 16_000];
+
+/// Builds an input from a repetition count.
+type Generator = fn(usize) -> String;
+
+fn measure(input: &str) -> Duration {
+  let options = wikidot_parser::Options::default();
+  let mut best = Duration::MAX;
+  for _ in 0..5 {
+    let begin = Instant::now();
+    std::hint::black_box(wikidot_parser::parse_with_options(input.to_string(), &options));
+    best = best.min(begin.elapsed());
+  }
+  best
+}
+
+fn main() {
+  let cases: [(&str, Generator); 7] = [
+    ("unterminated [[", |n| "[[".repeat(n)),
+    ("unterminated [[[", |n| "[[[".repeat(n)),
+    ("unterminated [", |n| "[https://example.com ".repeat(n)),
+    ("unterminated quote", |n| r#"[[span title=""#.repeat(n) + "]]"),
+    ("closed elements", |n| "[[span]]a[[/span]] ".repeat(n)),
+    ("unterminated module", |n| "[[module CSS]]".repeat(n)),
+    ("plain text", |n| "lorem ipsum ".repeat(n)),
+  ];
+
+  for (name, generate) in cases {
+    println!("{}", name);
+    for n in SIZES {
+      let input = generate(n);
+      let elapsed = measure(&input);
+      let len = input.chars().count();
+      println!("  {:>8} chars: {:>10.3?} ({:.1} ns/char)", len, elapsed, elapsed.as_nanos() as f64 / len as f64);
+    }
+  }
+}
@@ -11,8 +11,7 @@ impl From<CssSize> for String {
   }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Url(pub String); // TODO validate
+pub use crate::url::Url;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum WikidotColor {
@@ -56,6 +55,77 @@ impl WikidotColor {
       _ => { None }
     }
   }
+
+  pub fn rgb(self) -> (u8, u8, u8) {
+    match self {
+      Self::Aqua => (0x00, 0xFF, 0xFF),
+      Self::Black => (0x00, 0x00, 0x00),
+      Self::Blue => (0x00, 0x00, 0xFF),
+      Self::Fuchsia => (0xFF, 0x00, 0xFF), // magenta
+      Self::Grey => (0x80, 0x80, 0x80),
+      Self::Green => (0x00, 0x80, 0x00),
+      Self::Lime => (0x00, 0xFF, 0x00),
+      Self::Maroon => (0x80, 0x00, 0x00),
+      Self::Navy => (0x00, 0x00, 0x80),
+      Self::Olive => (0x80, 0x80, 0x00),
+      Self::Purple => (0x80, 0x00, 0x80),
+      Self::Red => (0xFF, 0x00, 0x00),
+      Self::Silver => (0xC0, 0xC0, 0xC0),
+      Self::Teal => (0x00, 0x80, 0x80),
+      Self::White => (0xFF, 0xFF, 0xFF),
+      Self::Yellow => (0xFF, 0xFF, 0x00),
+    }
+  }
+}
+
+/// Where the "hide" label of a collapsible is shown while it is unfolded (`hideLocation` attribute).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HideLocation {
+  Top,
+  Bottom,
+  Both,
+}
+
+impl HideLocation {
+  pub fn from(value: &str) -> Option<Self> {
+    match value.trim().to_lowercase().as_str() {
+      "top" => Some(Self::Top),
+      "bottom" => Some(Self::Bottom),
+      "both" => Some(Self::Both),
+      _ => None,
+    }
+  }
+}
+
+/// Text alignment of `[[=]]`, `[[<]]`, `[[>]]` and `[[==]]` blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+  Left,
+  Right,
+  Center,
+  Justify,
+}
+
+impl Alignment {
+  pub fn from(name: &str) -> Option<Self> {
+    match name.trim() {
+      "<" => Some(Self::Left),
+      ">" => Some(Self::Right),
+      "=" => Some(Self::Center),
+      "==" => Some(Self::Justify),
+      _ => None,
+    }
+  }
+
+  /// Value of the CSS `text-align` property.
+  pub fn as_css(&self) -> &'static str {
+    match self {
+      Self::Left => "left",
+      Self::Right => "right",
+      Self::Center => "center",
+      Self::Justify => "justify",
+    }
+  }
 }
 
 pub mod table_cell {
@@ -68,15 +138,8 @@ pub mod table_cell {
   }
 
   #[derive(Debug, Clone, PartialEq, Eq)]
-  pub struct BlockCell {
-    pub val: Vec<crate::tokenizer::Token>,
-    pub style: Option<Style>,
-    pub spanning: std::num::NonZeroUsize,
-  }
-
-  #[derive(Debug, Clone, PartialEq, Eq)]
-  pub struct Cell {
-    pub val: Vec<crate::ast::TreeElement>,
+  pub struct BlockCell<'a> {
+    pub val: Vec<crate::tokenizer::Token<'a>>,
     pub style: Option<Style>,
     pub spanning: std::num::NonZeroUsize,
   }
@@ -95,26 +158,104 @@ pub enum TreeElement {
   Subscript(Vec<TreeElement>),
   Colored{red: u8, green: u8, blue: u8, children: Vec<TreeElement>},
   Size{scale: CssSize, children: Vec<TreeElement>}, // scaleは有効なCSS値
-  Link{href: Url, open_in_new_tab: bool, name: String}, // TODO implement parsing name as wikidot string
-  Collapsible{text_open: String, text_closed: String, children: Vec<TreeElement>}, // TODO fix: show open/close message
+  Link{href: Url, open_in_new_tab: bool, missing: bool, children: Vec<TreeElement>}, // missingはリンク先のページが存在しない. 子にリンクは含まない
+  Collapsible{text_open: String, text_closed: String, folded: bool, hide_location: HideLocation, children: Vec<TreeElement>},
   Footnote(std::num::NonZeroUsize), // idは構文解析時に自動的に生成
+  Anchor(String), // [[# name]]. [#name text] でリンクされる
 
   FootnoteTarget(Vec<TreeElement>), // 子はFootnoteTargetChildのみ。 // TODO implement typical restriction
   FootnoteTargetChild{id: std::num::NonZeroUsize, children: Vec<TreeElement>},
 
   QuoteBlock(Vec<TreeElement>),
   Iframe(String), // the value is raw HTML element string
+  ExternalIframe{src: Url}, // [[iframe url]]
+  Image{src: Url, alt: String},
   Tab{
     title: String,
     children: Vec<TreeElement>,
   },
   TabView(Vec<TreeElement>), // only holds Tabs
-  Table(Vec<Vec<table_cell::Cell>>),
+  Align{alignment: Alignment, children: Vec<TreeElement>},
+  Heading{level: std::num::NonZeroUsize, children: Vec<TreeElement>}, // idは描画時に出現順で生成
+  TableOfContents, // lists the Headings of the page
+  HorizontalRule,
+  Stylesheet(String), // [[module CSS]]. sanitized. the renderer collects them into <head>
+  Module(crate::module::Module), // rendered by the handler in Options::modules
+  Table{attributes: Vec<(String, String)>, children: Vec<TreeElement>}, // only holds TableRows. attributes are sanitized
+  TableRow{attributes: Vec<(String, String)>, children: Vec<TreeElement>}, // only holds TableCells
+  TableCell{
+    style: Option<table_cell::Style>, // Title is a header cell
+    colspan: std::num::NonZeroUsize,
+    rowspan: std::num::NonZeroUsize,
+    attributes: Vec<(String, String)>,
+    children: Vec<TreeElement>,
+  },
+  DefinitionList(Vec<DefinitionItem>),
   NewLine,
 
   HtmlElement{tag: String, property: Vec<(String, String)>, children: Vec<TreeElement>},
 }
 
+/// (term, definition) of a definition list.
+pub type DefinitionItem = (Vec<TreeElement>, Vec<TreeElement>);
+
+impl TreeElement {
+  /// Child elements. Definition list items are not included.
+  pub fn children(&self) -> &[TreeElement] {
+    match self {
+      TreeElement::Paragraph(children)
+      | TreeElement::Bold(children)
+      | TreeElement::Italics(children)
+      | TreeElement::Underline(children)
+      | TreeElement::Strikethrough(children)
+      | TreeElement::Monospaced(children)
+      | TreeElement::Superscript(children)
+      | TreeElement::Subscript(children)
+      | TreeElement::Colored { children, .. }
+      | TreeElement::Size { children, .. }
+      | TreeElement::Collapsible { children, .. }
+      | TreeElement::FootnoteTarget(children)
+      | TreeElement::FootnoteTargetChild { children, .. }
+      | TreeElement::QuoteBlock(children)
+      | TreeElement::Tab { children, .. }
+      | TreeElement::TabView(children)
+      | TreeElement::Align { children, .. }
+      | TreeElement::Heading { children, .. }
+      | TreeElement::Table { children, .. }
+      | TreeElement::TableRow { children, .. }
+      | TreeElement::TableCell { children, .. }
+      | TreeElement::Link { children, .. }
+      | TreeElement::HtmlElement { children, .. } => children,
+
+      TreeElement::Text(_)
+      | TreeElement::Footnote(_)
+      | TreeElement::Anchor(_)
+      | TreeElement::Iframe(_)
+      | TreeElement::ExternalIframe { .. }
+      | TreeElement::Image { .. }
+      | TreeElement::TableOfContents
+      | TreeElement::HorizontalRule
+      | TreeElement::Stylesheet(_)
+      | TreeElement::Module(_)
+      | TreeElement::DefinitionList(_)
+      | TreeElement::NewLine => &[],
+    }
+  }
+
+  /// Concatenated text of the element and its descendants.
+  pub fn plain_text(&self) -> String {
+    let mut res = String::new();
+    let mut stack = vec![self];
+    while let Some(element) = stack.pop() {
+      match element {
+        TreeElement::Text(text) => { res += text; }
+        _ => { stack.extend(element.children().iter().rev()); }
+      }
+    }
+    res
+  }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ParseFrame {
   Paragraph,
@@ -127,15 +268,17 @@ pub enum ParseFrame {
   Subscript,
   Colored{red: u8, green: u8, blue: u8},
   Size{scale: CssSize},
-  // Link does not contain children
-  // InternalLink does not contain children
-  Collapsible{text_open: String, text_closed: String},
+  // Link is not a frame. Its label is parsed on its own
+  Collapsible{text_open: String, text_closed: String, folded: bool, hide_location: HideLocation},
   Footnote(std::num::NonZeroUsize), // TODO implement [[footnote]] syntax
   QuoteBlock,
   // Iframe is a single element. The values are written in HTML and they won't be parsed.
   Tab(String),
   TabView, // this is a div element internally, just for showing renderers begin of TabView
-  // Table does not contain TreeElement children
+  Align(Alignment),
+  Table{attributes: Vec<(String, String)>},
+  TableRow{attributes: Vec<(String, String)>},
+  TableCell{style: Option<table_cell::Style>, colspan: std::num::NonZeroUsize, rowspan: std::num::NonZeroUsize, attributes: Vec<(String, String)>},
 
   HtmlElement{tag: String, properties: Vec<(String, String)>}, // should be filtered by its tag
 }
@@ -153,11 +296,15 @@ impl ParseFrame {
       ParseFrame::Subscript => TreeElement::Subscript(children),
       ParseFrame::Colored{red, green, blue} => TreeElement::Colored{red, green, blue, children},
       ParseFrame::Size{scale} => TreeElement::Size{scale, children},
-      ParseFrame::Collapsible{text_open, text_closed} => TreeElement::Collapsible{text_open, text_closed, children},
+      ParseFrame::Collapsible{text_open, text_closed, folded, hide_location} => TreeElement::Collapsible{text_open, text_closed, folded, hide_location, children},
       ParseFrame::Footnote(id) => TreeElement::Footnote(id),
       ParseFrame::QuoteBlock => TreeElement::QuoteBlock(children),
       ParseFrame::Tab(title) => TreeElement::Tab{title, children},
       ParseFrame::TabView => TreeElement::TabView(children),
+      ParseFrame::Align(alignment) => TreeElement::Align { alignment, children },
+      ParseFrame::Table { attributes } => TreeElement::Table { attributes, children },
+      ParseFrame::TableRow { attributes } => TreeElement::TableRow { attributes, children },
+      ParseFrame::TableCell { style, colspan, rowspan, attributes } => TreeElement::TableCell { style, colspan, rowspan, attributes, children },
       ParseFrame::HtmlElement { tag, properties: property } => TreeElement::HtmlElement { tag, property, children },
     }
   }
@@ -179,6 +326,10 @@ impl ParseFrame {
       ParseFrame::QuoteBlock =>  ParseFrameKind::QuoteBlock,
       ParseFrame::Tab{..} => ParseFrameKind::Tab,
       ParseFrame::TabView => ParseFrameKind::TabView,
+      ParseFrame::Align(_) => ParseFrameKind::Align,
+      ParseFrame::Table{..} => ParseFrameKind::Table,
+      ParseFrame::TableRow{..} => ParseFrameKind::TableRow,
+      ParseFrame::TableCell{..} => ParseFrameKind::TableCell,
       ParseFrame::HtmlElement{tag, ..} => ParseFrameKind::HtmlElement{tag: tag.clone()},
     }
   }
@@ -201,5 +352,9 @@ pub enum ParseFrameKind {
   QuoteBlock,
   Tab,
   TabView,
+  Align,
+  Table,
+  TableRow,
+  TableCell,
   HtmlElement{tag: String},
 }
\ No newline at end of file
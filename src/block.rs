@@ -2,47 +2,87 @@ use crate::tokenizer::Token;
 use crate::ast::table_cell;
 
 mod parse_table;
+mod parse_heading;
+mod parse_definition_list;
 mod data_builder;
 
 #[derive(PartialEq, Eq, Debug)]
-pub enum BlockLevelAttribute {
-  BlockQuote(Vec<BlockLevelAttribute>),
-  Table(Vec<Vec<table_cell::BlockCell>>), // Inline以外中には入らないようにする必要がある.
-  TabView(Vec<BlockLevelAttribute>), // only contains Tabs
-  Tab{title: String, children: Vec<BlockLevelAttribute>},
-
-  Inline(Vec<crate::tokenizer::Token>), // トップレベルのInlineは段落を示す.
+pub enum BlockLevelAttribute<'a> {
+  BlockQuote(Vec<BlockLevelAttribute<'a>>),
+  Table(Vec<Vec<table_cell::BlockCell<'a>>>), // Inline以外中には入らないようにする必要がある.
+  TabView(Vec<BlockLevelAttribute<'a>>), // only contains Tabs
+  Align{alignment: crate::ast::Alignment, children: Vec<BlockLevelAttribute<'a>>}, // [[=]], [[<]], [[>]], [[==]]
+  Tab{title: String, children: Vec<BlockLevelAttribute<'a>>},
+  Heading{level: std::num::NonZeroUsize, children: Vec<crate::tokenizer::Token<'a>>},
+  AdvancedTable{attributes: Vec<(String, String)>, children: Vec<BlockLevelAttribute<'a>>}, // [[table]]. only contains TableRows
+  TableRow{attributes: Vec<(String, String)>, children: Vec<BlockLevelAttribute<'a>>}, // [[row]]. only contains TableCells
+  TableCell{header: bool, attributes: Vec<(String, String)>, children: Vec<BlockLevelAttribute<'a>>}, // [[cell]], [[hcell]]
+  TableOfContents,
+  HorizontalRule,
+  Stylesheet(std::borrow::Cow<'a, str>), // [[module CSS]]. raw CSS
+  Module{name: std::borrow::Cow<'a, str>, args: Vec<(std::borrow::Cow<'a, str>, std::borrow::Cow<'a, str>)>, body: Option<std::borrow::Cow<'a, str>>, position: usize},
+  DefinitionList(Vec<(Vec<crate::tokenizer::Token<'a>>, Vec<crate::tokenizer::Token<'a>>)>), // (term, definition)
+
+  Inline(Vec<crate::tokenizer::Token<'a>>), // トップレベルのInlineは段落を示す.
 }
 
 pub enum BlockLevelFrame {
   BlockQuote,
   TabView,
   Tab{title: String},
+  Align(crate::ast::Alignment),
+  AdvancedTable{attributes: Vec<(String, String)>},
+  TableRow{attributes: Vec<(String, String)>},
+  TableCell{header: bool, attributes: Vec<(String, String)>},
   // Table ... trailing element
   // Inline ... trailing element
 }
 
 
-pub fn parse(tokens: Vec<crate::tokenizer::Token>) -> Vec<BlockLevelAttribute> {
-  let mut db = data_builder::DataBuilder::new();
+fn owned_attributes(attributes: &[(std::borrow::Cow<'_, str>, std::borrow::Cow<'_, str>)]) -> Vec<(String, String)> {
+  attributes.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+}
+
+#[cfg(test)]
+pub fn parse<'a>(tokens: impl IntoIterator<Item = Token<'a>>) -> Vec<BlockLevelAttribute<'a>> {
+  BlockParser::new(tokens.into_iter()).collect()
+}
+
+/// Parses blocks from a token stream. Top-level blocks are returned as soon as they are complete,
+/// so the rest of the stream is not read until it is needed.
+pub struct BlockParser<'a, I: Iterator<Item = Token<'a>>> {
+  tokens: I,
+  db: data_builder::DataBuilder<'a>,
+  is_last_newline: bool,
+  finished: bool,
+}
+
+impl<'a, I: Iterator<Item = Token<'a>>> BlockParser<'a, I> {
+  pub fn new(tokens: I) -> Self {
+    Self {
+      tokens,
+      db: data_builder::DataBuilder::new(),
+      is_last_newline: false,
+      finished: false,
+    }
+  }
 
-  let mut is_last_newline = false;
-  for token in tokens {
+  fn feed(&mut self, token: Token<'a>) {
     match token {
       Token::BlockQuote(level) => {
-        db.set_bq_depth(level.get());
+        self.db.set_bq_depth(level.get());
 
-        is_last_newline = false;
+        self.is_last_newline = false;
       }
 
-      Token::ElementBegin { ref name, ref attributes } => {
-        if is_last_newline {
-          db.set_bq_depth(0);
+      Token::ElementBegin { ref name, ref attributes, .. } => {
+        if self.is_last_newline {
+          self.db.set_bq_depth(0);
         }
 
-        match name.as_str() {
+        match name.as_ref() {
           "tabview" => {
-            db.push(BlockLevelFrame::TabView);
+            self.db.push(BlockLevelFrame::TabView);
           }
 
           "tab" => {
@@ -56,65 +96,165 @@ pub fn parse(tokens: Vec<crate::tokenizer::Token>) -> Vec<BlockLevelAttribute> {
               }
             }
 
-            db.push(BlockLevelFrame::Tab { title });
+            self.db.push(BlockLevelFrame::Tab { title });
+          }
+
+          "toc" => {
+            self.db.add(BlockLevelAttribute::TableOfContents);
+          }
+
+          "=" | "<" | ">" | "==" => {
+            if let Some(alignment) = crate::ast::Alignment::from(name) {
+              self.db.push(BlockLevelFrame::Align(alignment));
+            }
+          }
+
+          "table" => {
+            self.db.push(BlockLevelFrame::AdvancedTable { attributes: owned_attributes(attributes) });
+          }
+
+          "row" if matches!(self.db.get_last_frame(), Some(BlockLevelFrame::AdvancedTable { .. })) => {
+            self.db.push(BlockLevelFrame::TableRow { attributes: owned_attributes(attributes) });
+          }
+
+          "cell" | "hcell" if matches!(self.db.get_last_frame(), Some(BlockLevelFrame::TableRow { .. })) => {
+            self.db.push(BlockLevelFrame::TableCell { header: name == "hcell", attributes: owned_attributes(attributes) });
           }
 
           &_ => {
-            db.add_token(token);
+            self.db.add_token(token);
           }
         }
 
-        is_last_newline = false;
+        self.is_last_newline = false;
       }
 
       Token::ElementEnd(ref name) => {
-        if is_last_newline {
-          db.set_bq_depth(0);
+        if self.is_last_newline {
+          self.db.set_bq_depth(0);
         }
 
-        match name.as_str() {
+        match name.as_ref() {
           "tabview" => {
-            if let Some(BlockLevelFrame::TabView) = db.get_last_frame() {
-              db.pop_and_merge();
+            if let Some(BlockLevelFrame::TabView) = self.db.get_last_frame() {
+              self.db.pop_and_merge();
             }
           }
 
           "tab" => {
-            if let Some(BlockLevelFrame::Tab { title: _ }) = db.get_last_frame() {
-              db.pop_and_merge();
+            if let Some(BlockLevelFrame::Tab { title: _ }) = self.db.get_last_frame() {
+              self.db.pop_and_merge();
+            }
+          }
+
+          "=" | "<" | ">" | "==" => {
+            if let Some(BlockLevelFrame::Align(alignment)) = self.db.get_last_frame() && Some(*alignment) == crate::ast::Alignment::from(name) {
+              self.db.pop_and_merge();
+            }
+          }
+
+          "table" => {
+            if let Some(BlockLevelFrame::AdvancedTable { .. }) = self.db.get_last_frame() {
+              self.db.pop_and_merge();
+            }
+          }
+
+          "row" => {
+            if let Some(BlockLevelFrame::TableRow { .. }) = self.db.get_last_frame() {
+              self.db.pop_and_merge();
+            }
+          }
+
+          "cell" | "hcell" => {
+            if let Some(BlockLevelFrame::TableCell { header, .. }) = self.db.get_last_frame() && *header == (name == "hcell") {
+              self.db.pop_and_merge();
             }
           }
 
           &_ => {
-            db.add_token(token);
+            self.db.add_token(token);
           }
         }
 
-        is_last_newline = false;
+        self.is_last_newline = false;
+      }
+
+      Token::HorizontalRule => {
+        if self.is_last_newline {
+          self.db.set_bq_depth(0);
+        }
+
+        self.db.add(BlockLevelAttribute::HorizontalRule);
+        self.is_last_newline = false;
+      }
+
+      Token::Stylesheet(css) => {
+        if self.is_last_newline {
+          self.db.set_bq_depth(0);
+        }
+
+        self.db.add(BlockLevelAttribute::Stylesheet(css));
+        self.is_last_newline = false;
+      }
+
+      Token::Module { name, args, body, position } => {
+        if self.is_last_newline {
+          self.db.set_bq_depth(0);
+        }
+
+        // 行の途中にあるものは段落 (表のセルなど) の中に残す
+        if self.is_last_newline || self.db.at_line_start() {
+          self.db.add(BlockLevelAttribute::Module { name, args, body, position });
+        } else {
+          self.db.add_token(Token::Module { name, args, body, position });
+        }
+        self.is_last_newline = false;
       }
 
       Token::NewLine => {
-        if is_last_newline {
-          db.flush();
-          db.set_bq_depth(0);
+        if self.is_last_newline {
+          self.db.flush();
+          self.db.set_bq_depth(0);
         } else {
-          db.add_token(token);
+          self.db.add_token(token);
         }
 
-        is_last_newline = true;
+        self.is_last_newline = true;
       }
 
       _ => {
-        if is_last_newline {
-          db.set_bq_depth(0);
+        if self.is_last_newline {
+          self.db.set_bq_depth(0);
         }
 
-        db.add_token(token);
+        self.db.add_token(token);
+        self.is_last_newline = false;
       }
     }
   }
+}
 
-  db.get()
+impl<'a, I: Iterator<Item = Token<'a>>> Iterator for BlockParser<'a, I> {
+  type Item = BlockLevelAttribute<'a>;
+
+  fn next(&mut self) -> Option<BlockLevelAttribute<'a>> {
+    loop {
+      if let Some(block) = self.db.pop_completed() {
+        return Some(block);
+      }
+      if self.finished {
+        return None;
+      }
+
+      match self.tokens.next() {
+        Some(token) => { self.feed(token); }
+        None => {
+          self.db.finish();
+          self.finished = true;
+        }
+      }
+    }
+  }
 }
 
 #[cfg(test)]
@@ -125,8 +265,8 @@ mod tests {
     std::num::NonZeroUsize::try_from(v).unwrap()
   }
 
-  fn sf(st: &str) -> String {
-    String::from(st)
+  fn sf(st: &str) -> std::borrow::Cow<'_, str> {
+    std::borrow::Cow::Borrowed(st)
   }
 
   #[test]
@@ -149,13 +289,13 @@ mod tests {
   fn test_blockquote() {
     use crate::tokenizer::Token;
     assert_eq!(parse(vec![
-      Token::BlockQuote(nz(1)), Token::Text(String::from("Hello,")), Token::NewLine,
-      Token::BlockQuote(nz(2)), Token::Text(String::from("World!")), Token::NewLine
+      Token::BlockQuote(nz(1)), Token::Text("Hello,".into()), Token::NewLine,
+      Token::BlockQuote(nz(2)), Token::Text("World!".into()), Token::NewLine
     ]), vec![
       BlockLevelAttribute::BlockQuote(vec![
-        BlockLevelAttribute::Inline(vec![Token::Text(String::from("Hello,"))]),
+        BlockLevelAttribute::Inline(vec![Token::Text("Hello,".into())]),
         BlockLevelAttribute::BlockQuote(vec![
-          BlockLevelAttribute::Inline(vec![Token::Text(String::from("World!"))])
+          BlockLevelAttribute::Inline(vec![Token::Text("World!".into())])
         ]),
       ])
     ]);
@@ -166,18 +306,18 @@ mod tests {
     use crate::tokenizer::tokenize;
     use crate::tokenizer::Token;
     // "a\n|| a || b ||\nc"
-    let tokens = tokenize(String::from("a\n|| a || b ||\nc"));
+    let tokens = tokenize("a\n|| a || b ||\nc");
     let parsed = parse(tokens);
 
     assert_eq!(parsed, vec![
-      BlockLevelAttribute::Inline(vec![Token::Text(String::from("a"))]),
+      BlockLevelAttribute::Inline(vec![Token::Text("a".into())]),
       BlockLevelAttribute::Table(vec![
         vec![
-          crate::block::table_cell::BlockCell { val: vec![Token::Text(String::from(" a "))], style: None, spanning: nz(1) },
-          crate::block::table_cell::BlockCell { val: vec![Token::Text(String::from(" b "))], style: None, spanning: nz(1) },
+          crate::block::table_cell::BlockCell { val: vec![Token::Text(" a ".into())], style: None, spanning: nz(1) },
+          crate::block::table_cell::BlockCell { val: vec![Token::Text(" b ".into())], style: None, spanning: nz(1) },
         ]
       ]),
-      BlockLevelAttribute::Inline(vec![Token::Text(String::from("c"))]),
+      BlockLevelAttribute::Inline(vec![Token::Text("c".into())]),
     ]);
   }
 
@@ -186,24 +326,24 @@ mod tests {
     use crate::tokenizer::Token;
     // depth 1 -> 2 -> 3 then close to 1 and continue
     let tokens = vec![
-      Token::BlockQuote(nz(1)), Token::Text(String::from("L1")), Token::NewLine,
-      Token::BlockQuote(nz(2)), Token::Text(String::from("L2")), Token::NewLine,
-      Token::BlockQuote(nz(3)), Token::Text(String::from("L3")), Token::NewLine,
-      Token::BlockQuote(nz(1)), Token::Text(String::from("After")), Token::NewLine,
+      Token::BlockQuote(nz(1)), Token::Text("L1".into()), Token::NewLine,
+      Token::BlockQuote(nz(2)), Token::Text("L2".into()), Token::NewLine,
+      Token::BlockQuote(nz(3)), Token::Text("L3".into()), Token::NewLine,
+      Token::BlockQuote(nz(1)), Token::Text("After".into()), Token::NewLine,
     ];
 
     let parsed = parse(tokens);
 
     assert_eq!(parsed, vec![
       BlockLevelAttribute::BlockQuote(vec![
-        BlockLevelAttribute::Inline(vec![Token::Text(String::from("L1"))]),
+        BlockLevelAttribute::Inline(vec![Token::Text("L1".into())]),
         BlockLevelAttribute::BlockQuote(vec![
-          BlockLevelAttribute::Inline(vec![Token::Text(String::from("L2"))]),
+          BlockLevelAttribute::Inline(vec![Token::Text("L2".into())]),
           BlockLevelAttribute::BlockQuote(vec![
-            BlockLevelAttribute::Inline(vec![Token::Text(String::from("L3"))])
+            BlockLevelAttribute::Inline(vec![Token::Text("L3".into())])
           ]),
         ]),
-        BlockLevelAttribute::Inline(vec![Token::Text(String::from("After"))]),
+        BlockLevelAttribute::Inline(vec![Token::Text("After".into())]),
       ])
     ]);
   }
@@ -213,26 +353,26 @@ mod tests {
     use crate::tokenizer::Token;
 
     let tokens = vec![
-      Token::ElementBegin { name: String::from("tabview"), attributes: vec![] },
-      Token::ElementBegin { name: String::from("tab"), attributes: vec![(String::from(""), String::from("Tab 1"))] },
-      Token::Text(String::from("txt 1")),
-      Token::ElementEnd(String::from("tab")),
-      Token::ElementBegin { name: String::from("tab"), attributes: vec![(String::from(""), String::from("Tab 2"))] },
-      Token::Text(String::from("txt 2")),
-      Token::ElementEnd(String::from("tab")),
-      Token::ElementBegin { name: String::from("tab"), attributes: vec![(String::from(""), String::from("Tab 3"))] },
-      Token::Text(String::from("txt 3")),
-      Token::ElementEnd(String::from("tab")),
-      Token::ElementEnd(String::from("tabview")),
+      Token::ElementBegin { name: "tabview".into(), attributes: vec![], arguments: vec![] },
+      Token::ElementBegin { name: "tab".into(), attributes: vec![("".into(), "Tab 1".into())], arguments: vec![] },
+      Token::Text("txt 1".into()),
+      Token::ElementEnd("tab".into()),
+      Token::ElementBegin { name: "tab".into(), attributes: vec![("".into(), "Tab 2".into())], arguments: vec![] },
+      Token::Text("txt 2".into()),
+      Token::ElementEnd("tab".into()),
+      Token::ElementBegin { name: "tab".into(), attributes: vec![("".into(), "Tab 3".into())], arguments: vec![] },
+      Token::Text("txt 3".into()),
+      Token::ElementEnd("tab".into()),
+      Token::ElementEnd("tabview".into()),
     ];
 
     let parsed = parse(tokens);
 
     assert_eq!(parsed, vec![
       BlockLevelAttribute::TabView(vec![
-        BlockLevelAttribute::Tab{title: String::from("Tab 1"), children: vec![BlockLevelAttribute::Inline(vec![Token::Text(String::from("txt 1"))])]},
-        BlockLevelAttribute::Tab{title: String::from("Tab 2"), children: vec![BlockLevelAttribute::Inline(vec![Token::Text(String::from("txt 2"))])]},
-        BlockLevelAttribute::Tab{title: String::from("Tab 3"), children: vec![BlockLevelAttribute::Inline(vec![Token::Text(String::from("txt 3"))])]},
+        BlockLevelAttribute::Tab{title: String::from("Tab 1"), children: vec![BlockLevelAttribute::Inline(vec![Token::Text("txt 1".into())])]},
+        BlockLevelAttribute::Tab{title: String::from("Tab 2"), children: vec![BlockLevelAttribute::Inline(vec![Token::Text("txt 2".into())])]},
+        BlockLevelAttribute::Tab{title: String::from("Tab 3"), children: vec![BlockLevelAttribute::Inline(vec![Token::Text("txt 3".into())])]},
       ])
     ]);
   }
@@ -243,14 +383,14 @@ mod tests {
 
     let tokens = vec![
       Token::BlockQuote(nz(1)),
-      Token::ElementBegin { name: String::from("tabview"), attributes: vec![] },
-      Token::ElementBegin { name: String::from("tab"), attributes: vec![(String::from(""), String::from("Tab 1"))] },
-      Token::Text(String::from("txt 1")),
-      Token::ElementEnd(String::from("tab")),
-      Token::ElementBegin { name: String::from("tab"), attributes: vec![(String::from(""), String::from("Tab 2"))] },
-      Token::Text(String::from("txt 2")),
-      Token::ElementEnd(String::from("tab")),
-      Token::ElementEnd(String::from("tabview")),
+      Token::ElementBegin { name: "tabview".into(), attributes: vec![], arguments: vec![] },
+      Token::ElementBegin { name: "tab".into(), attributes: vec![("".into(), "Tab 1".into())], arguments: vec![] },
+      Token::Text("txt 1".into()),
+      Token::ElementEnd("tab".into()),
+      Token::ElementBegin { name: "tab".into(), attributes: vec![("".into(), "Tab 2".into())], arguments: vec![] },
+      Token::Text("txt 2".into()),
+      Token::ElementEnd("tab".into()),
+      Token::ElementEnd("tabview".into()),
     ];
 
     let parsed = parse(tokens);
@@ -258,10 +398,182 @@ mod tests {
     assert_eq!(parsed, vec![
       BlockLevelAttribute::BlockQuote(vec![
         BlockLevelAttribute::TabView(vec![
-          BlockLevelAttribute::Tab{title: String::from("Tab 1"), children: vec![BlockLevelAttribute::Inline(vec![Token::Text(String::from("txt 1"))])]},
-          BlockLevelAttribute::Tab{title: String::from("Tab 2"), children: vec![BlockLevelAttribute::Inline(vec![Token::Text(String::from("txt 2"))])]},
+          BlockLevelAttribute::Tab{title: String::from("Tab 1"), children: vec![BlockLevelAttribute::Inline(vec![Token::Text("txt 1".into())])]},
+          BlockLevelAttribute::Tab{title: String::from("Tab 2"), children: vec![BlockLevelAttribute::Inline(vec![Token::Text("txt 2".into())])]},
         ]),
       ])
     ]);
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn test_align() {
+    use crate::tokenizer::tokenize;
+
+    let parsed = parse(tokenize("[[=]]\na\n\nb\n[[/=]]\n[[==]]\nc\n[[/=]]\n[[/==]]"));
+
+    assert_eq!(parsed, vec![
+      BlockLevelAttribute::Align { alignment: crate::ast::Alignment::Center, children: vec![
+        BlockLevelAttribute::Inline(vec![Token::Text(sf("a"))]),
+        BlockLevelAttribute::Inline(vec![Token::Text(sf("b"))]),
+      ]},
+      BlockLevelAttribute::Align { alignment: crate::ast::Alignment::Justify, children: vec![
+        BlockLevelAttribute::Inline(vec![Token::Text(sf("c"))]),
+      ]},
+    ]);
+  }
+
+  #[test]
+  fn test_advanced_table() {
+    use crate::tokenizer::tokenize;
+
+    let parsed = parse(tokenize("[[table style=\"width: 100%\"]]\n[[row]]\n[[hcell colspan=\"2\"]]\n+ Title\n\na\n[[/hcell]] [[cell]]\n[[table]][[row]][[cell]]b[[/cell]][[/row]][[/table]]\n[[/cell]]\n[[/row]]\n[[/table]]\nc"));
+
+    assert_eq!(parsed, vec![
+      BlockLevelAttribute::AdvancedTable { attributes: vec![(String::from("style"), String::from("width: 100%"))], children: vec![
+        BlockLevelAttribute::TableRow { attributes: vec![], children: vec![
+          BlockLevelAttribute::TableCell { header: true, attributes: vec![(String::from("colspan"), String::from("2"))], children: vec![
+            BlockLevelAttribute::Heading { level: nz(1), children: vec![Token::Text(sf("Title"))] },
+            BlockLevelAttribute::Inline(vec![Token::Text(sf("a"))]),
+          ]},
+          BlockLevelAttribute::TableCell { header: false, attributes: vec![], children: vec![
+            BlockLevelAttribute::AdvancedTable { attributes: vec![], children: vec![
+              BlockLevelAttribute::TableRow { attributes: vec![], children: vec![
+                BlockLevelAttribute::TableCell { header: false, attributes: vec![], children: vec![
+                  BlockLevelAttribute::Inline(vec![Token::Text(sf("b"))]),
+                ]},
+              ]},
+            ]},
+          ]},
+        ]},
+      ]},
+      BlockLevelAttribute::Inline(vec![Token::Text(sf("c"))]),
+    ]);
+  }
+
+  #[test]
+  fn test_text_outside_of_cells() {
+    use crate::tokenizer::tokenize;
+
+    // ブラウザと同じく表の前に出す
+    let parsed = parse(tokenize("[[table]]\na\n[[row]] [[cell]]b[[/cell]]\nc\n[[/row]]\n[[/table]]"));
+
+    assert_eq!(parsed, vec![
+      BlockLevelAttribute::Inline(vec![Token::Text(sf("a"))]),
+      BlockLevelAttribute::Inline(vec![Token::Text(sf("c"))]),
+      BlockLevelAttribute::AdvancedTable { attributes: vec![], children: vec![
+        BlockLevelAttribute::TableRow { attributes: vec![], children: vec![
+          BlockLevelAttribute::TableCell { header: false, attributes: vec![], children: vec![
+            BlockLevelAttribute::Inline(vec![Token::Text(sf("b"))]),
+          ]},
+        ]},
+      ]},
+    ]);
+  }
+
+  #[test]
+  fn test_cell_outside_of_row() {
+    use crate::tokenizer::tokenize;
+
+    let parsed = parse(tokenize("[[cell]]a[[/cell]]"));
+
+    assert_eq!(parsed, vec![
+      BlockLevelAttribute::Inline(vec![
+        Token::ElementBegin { name: sf("cell"), attributes: vec![], arguments: vec![] },
+        Token::Text(sf("a")),
+      ]),
+    ]);
+  }
+
+  #[test]
+  fn test_table_of_contents() {
+    use crate::tokenizer::tokenize;
+
+    let parsed = parse(tokenize("[[toc]]\n+ Title\ntext"));
+
+    assert_eq!(parsed, vec![
+      BlockLevelAttribute::TableOfContents,
+      BlockLevelAttribute::Heading { level: nz(1), children: vec![Token::Text(sf("Title"))] },
+      BlockLevelAttribute::Inline(vec![Token::Text(sf("text"))]),
+    ]);
+  }
+
+  #[test]
+  fn test_horizontal_rule() {
+    use crate::tokenizer::tokenize;
+
+    assert_eq!(parse(tokenize("a\n----\nb")), vec![
+      BlockLevelAttribute::Inline(vec![Token::Text(sf("a"))]),
+      BlockLevelAttribute::HorizontalRule,
+      BlockLevelAttribute::Inline(vec![Token::Text(sf("b"))]),
+    ]);
+  }
+
+  #[test]
+  fn test_definition_list() {
+    use crate::tokenizer::tokenize;
+
+    assert_eq!(parse(tokenize("+ Title\n: a : b\n\n: c : d")), vec![
+      BlockLevelAttribute::Heading { level: nz(1), children: vec![Token::Text(sf("Title"))] },
+      BlockLevelAttribute::DefinitionList(vec![(vec![Token::Text(sf("a"))], vec![Token::Text(sf("b"))])]),
+      BlockLevelAttribute::DefinitionList(vec![(vec![Token::Text(sf("c"))], vec![Token::Text(sf("d"))])]),
+    ]);
+  }
+
+  #[test]
+  fn test_stylesheet() {
+    use crate::tokenizer::tokenize;
+
+    assert_eq!(parse(tokenize("a\n[[module CSS]]\n.b { --c: 1; }\n[[/module]]\nd")), vec![
+      BlockLevelAttribute::Inline(vec![Token::Text(sf("a"))]),
+      BlockLevelAttribute::Stylesheet(sf("\n.b { --c: 1; }\n")),
+      BlockLevelAttribute::Inline(vec![Token::Text(sf("d"))]),
+    ]);
+  }
+
+  #[test]
+  fn test_module() {
+    use crate::tokenizer::tokenize;
+
+    assert_eq!(parse(tokenize("a\n[[module Rate]]\nb")), vec![
+      BlockLevelAttribute::Inline(vec![Token::Text(sf("a"))]),
+      BlockLevelAttribute::Module { name: sf("Rate"), args: vec![], body: None, position: 2 },
+      BlockLevelAttribute::Inline(vec![Token::Text(sf("b"))]),
+    ]);
+  }
+
+  #[test]
+  fn test_module_in_line() {
+    use crate::tokenizer::tokenize;
+
+    assert_eq!(parse(tokenize("a [[module Rate]] b")), vec![
+      BlockLevelAttribute::Inline(vec![
+        Token::Text(sf("a ")),
+        Token::Module { name: sf("Rate"), args: vec![], body: None, position: 2 },
+        Token::Text(sf(" b")),
+      ]),
+    ]);
+  }
+
+  #[test]
+  fn test_paragraph_with_newlines() {
+    use crate::tokenizer::tokenize;
+
+    assert_eq!(parse(tokenize("a\nb\nc\n\nd")), vec![
+      BlockLevelAttribute::Inline(vec![Token::Text(sf("a")), Token::NewLine, Token::Text(sf("b")), Token::NewLine, Token::Text(sf("c"))]),
+      BlockLevelAttribute::Inline(vec![Token::Text(sf("d"))]),
+    ]);
+
+    // 3行目以降も同じ段落に入る
+    assert_eq!(parse(tokenize("a\n**b**\nc\nd")).len(), 1);
+  }
+
+  #[test]
+  fn test_stop_after_first_block() {
+    use crate::tokenizer::Tokenizer;
+    let mut tokens = Tokenizer::new("a\n\nb\n\nc");
+    let first = BlockParser::new(&mut tokens).next();
+    assert_eq!(first, Some(BlockLevelAttribute::Inline(vec![Token::Text(sf("a"))])));
+    // the rest is not tokenized yet
+    assert_eq!(tokens.next(), Some(Token::Text(sf("b"))));
+  }
+}
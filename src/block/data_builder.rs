@@ -2,24 +2,24 @@ use crate::tokenizer::Token;
 
 use super::{BlockLevelAttribute, BlockLevelFrame};
 
-pub struct DataBuilder {
-  root: Vec<BlockLevelAttribute>,
-  data: Vec<(BlockLevelFrame, Vec<BlockLevelAttribute>)>,
-  buf: Vec<Token>,
+pub struct DataBuilder<'a> {
+  root: std::collections::VecDeque<BlockLevelAttribute<'a>>, // 完成したトップレベルのブロック
+  data: Vec<(BlockLevelFrame, Vec<BlockLevelAttribute<'a>>)>,
+  buf: Vec<Token<'a>>,
   blockquote_depth_count: usize
 }
 
-impl DataBuilder {
+impl<'a> DataBuilder<'a> {
   pub fn new() -> Self {
     Self {
-      root: vec![],
+      root: std::collections::VecDeque::new(),
       data: vec![],
       buf: vec![],
       blockquote_depth_count: 0
     }
   }
 
-  fn pop(&mut self) -> Option<(BlockLevelFrame, Vec<BlockLevelAttribute>)> {
+  fn pop(&mut self) -> Option<(BlockLevelFrame, Vec<BlockLevelAttribute<'a>>)> {
     let v = self.data.pop();
     if let Some(val) = &v && let BlockLevelFrame::BlockQuote = val.0 {
       self.blockquote_depth_count-=1;
@@ -32,16 +32,18 @@ impl DataBuilder {
     while let Some(Token::NewLine) = self.buf.last() {
       self.buf.pop();
     }
+    // [[table]], [[row]] の直下にはセルしか置けない. セルの間の空白は捨て, それ以外の文字はブラウザと同じく表の前に出す
+    let in_table = matches!(self.get_last_frame(), Some(BlockLevelFrame::AdvancedTable { .. } | BlockLevelFrame::TableRow { .. }));
+    if in_table && self.buf.iter().all(|v| matches!(v, Token::NewLine) || matches!(v, Token::Text(text) if text.trim().is_empty())) {
+      self.buf.clear();
+    }
     if !self.buf.is_empty() {
-      let target: &mut Vec<BlockLevelAttribute>;
-      if let Some(pt) = self.data.last_mut() {
-        target = &mut pt.1;
-      } else {
-        target = &mut self.root;
-      }
-
-      for v in super::parse_table::parse_table(&mut self.buf) {
-        target.push(v);
+      let target = self.data.iter().rposition(|(frame, _)| !matches!(frame, BlockLevelFrame::AdvancedTable { .. } | BlockLevelFrame::TableRow { .. }));
+      for v in super::parse_heading::parse_heading(&mut self.buf) {
+        match target {
+          Some(target) => { self.data[target].1.push(v); }
+          None => { self.root.push_back(v); }
+        }
       }
     }
   }
@@ -50,25 +52,20 @@ impl DataBuilder {
     self.flush();
 
     if let Some((now_frame, now_children)) = self.pop() {
-      let push_target: &mut Vec<BlockLevelAttribute>;
+      let block = match now_frame {
+        BlockLevelFrame::BlockQuote => BlockLevelAttribute::BlockQuote(now_children),
+        BlockLevelFrame::TabView => BlockLevelAttribute::TabView(now_children),
+        BlockLevelFrame::Tab { title } => BlockLevelAttribute::Tab { title, children: now_children },
+        BlockLevelFrame::Align(alignment) => BlockLevelAttribute::Align { alignment, children: now_children },
+        BlockLevelFrame::AdvancedTable { attributes } => BlockLevelAttribute::AdvancedTable { attributes, children: now_children },
+        BlockLevelFrame::TableRow { attributes } => BlockLevelAttribute::TableRow { attributes, children: now_children },
+        BlockLevelFrame::TableCell { header, attributes } => BlockLevelAttribute::TableCell { header, attributes, children: now_children },
+      };
+
       if let Some(ar) = self.data.last_mut() {
-        push_target = &mut ar.1;
+        ar.1.push(block);
       } else {
-        push_target = &mut self.root;
-      }
-
-      match now_frame {
-        BlockLevelFrame::BlockQuote => {
-          push_target.push(BlockLevelAttribute::BlockQuote(now_children));
-        }
-
-        BlockLevelFrame::TabView => {
-          push_target.push(BlockLevelAttribute::TabView(now_children));
-        }
-
-        BlockLevelFrame::Tab { title } => {
-          push_target.push(BlockLevelAttribute::Tab { title, children: now_children });
-        }
+        self.root.push_back(block);
       }
       true
     } else {
@@ -85,21 +82,28 @@ impl DataBuilder {
     self.data.push((frame, vec![]));
   }
 
-  pub fn add(&mut self, data: BlockLevelAttribute) {
+  pub fn add(&mut self, data: BlockLevelAttribute<'a>) {
     self.flush();
 
     if let Some((_, target)) = self.data.last_mut() {
       target.push(data);
+    } else {
+      self.root.push_back(data);
     }
   }
 
-  pub fn add_token(&mut self, token: Token) {
+  pub fn add_token(&mut self, token: Token<'a>) {
     if self.buf.last().is_none_or(|v| *v == Token::NewLine) && token == Token::NewLine {
     } else {
       self.buf.push(token);
     }
   }
 
+  /// 行の途中でなければ true
+  pub fn at_line_start(&self) -> bool {
+    self.buf.last().is_none_or(|v| *v == Token::NewLine)
+  }
+
   fn stack_is_empty(&self) -> bool {
     self.data.is_empty()
   }
@@ -126,17 +130,14 @@ impl DataBuilder {
     }
   }
 
-  pub fn get_last_frame_mut(&mut self) -> Option<&mut BlockLevelFrame> {
-    if let Some(v) = self.data.last_mut() {
-      Some(&mut v.0)
-    } else {
-      None
-    }
+  /// Takes the oldest top-level block which will not change anymore.
+  pub fn pop_completed(&mut self) -> Option<BlockLevelAttribute<'a>> {
+    self.root.pop_front()
   }
 
-  pub fn get(mut self) -> Vec<BlockLevelAttribute> {
+  /// Closes everything at the end of the input.
+  pub fn finish(&mut self) {
     self.flush();
     while self.pop_and_merge() {}
-    self.root
   }
 }
\ No newline at end of file
@@ -0,0 +1,112 @@
+use crate::tokenizer::Token;
+
+use super::BlockLevelAttribute;
+
+/// Splits definition list lines (`: term : definition`) out of the buffer. The other lines are passed to `parse_table`.
+pub fn parse_definition_list<'a>(buf: &mut Vec<Token<'a>>) -> Vec<BlockLevelAttribute<'a>> {
+  let buf = std::mem::take(buf);
+
+  let mut res: Vec<BlockLevelAttribute> = vec![];
+  let mut now_buf: Vec<Token> = vec![];
+  let mut list: Vec<(Vec<Token>, Vec<Token>)> = vec![];
+  let mut item: Option<(Vec<Token>, Option<Vec<Token>>)> = None; // (term, definition)
+
+  for token in buf {
+    match token {
+      Token::DefinitionTerm if item.is_none() => {
+        // 前までの要素を書き出す
+        while let Some(Token::NewLine) = now_buf.last() {
+          now_buf.pop();
+        }
+        res.append(&mut super::parse_table::parse_table(&mut now_buf));
+
+        item = Some((vec![], None));
+      }
+
+      Token::DefinitionSeparator if matches!(item, Some((_, None))) => {
+        if let Some((_, definition)) = &mut item {
+          *definition = Some(vec![]);
+        }
+      }
+
+      Token::NewLine if item.is_some() => {
+        if !finish_item(item.take().unwrap(), &mut list, &mut now_buf, &mut res) {
+          now_buf.push(token);
+        }
+      }
+
+      _ => {
+        if let Some((term, definition)) = &mut item {
+          definition.as_mut().unwrap_or(term).push(token);
+        } else {
+          if !list.is_empty() {
+            res.push(BlockLevelAttribute::DefinitionList(std::mem::take(&mut list)));
+          }
+          now_buf.push(token);
+        }
+      }
+    }
+  }
+
+  if let Some(item) = item {
+    finish_item(item, &mut list, &mut now_buf, &mut res);
+  }
+  if !list.is_empty() {
+    res.push(BlockLevelAttribute::DefinitionList(list));
+  }
+  res.append(&mut super::parse_table::parse_table(&mut now_buf));
+
+  res
+}
+
+/// Adds a finished line to the list. A line without ` : ` is not a definition and is kept as text.
+/// Returns whether the line was a definition.
+fn finish_item<'a>(
+  (term, definition): (Vec<Token<'a>>, Option<Vec<Token<'a>>>),
+  list: &mut Vec<(Vec<Token<'a>>, Vec<Token<'a>>)>,
+  now_buf: &mut Vec<Token<'a>>,
+  res: &mut Vec<BlockLevelAttribute<'a>>,
+) -> bool {
+  match definition {
+    Some(definition) => {
+      list.push((term, definition));
+      true
+    }
+    None => {
+      if !list.is_empty() {
+        res.push(BlockLevelAttribute::DefinitionList(std::mem::take(list)));
+      }
+      now_buf.push(Token::Text(": ".into()));
+      now_buf.extend(term);
+      false
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::tokenizer;
+
+  #[test]
+  fn test_definition_list_between_paragraphs() {
+    assert_eq!(parse_definition_list(&mut tokenizer::tokenize("a\n: x : **y**\n: z : w\nb")), vec![
+      BlockLevelAttribute::Inline(vec![Token::Text("a".into())]),
+      BlockLevelAttribute::DefinitionList(vec![
+        (vec![Token::Text("x".into())], vec![Token::Bold, Token::Text("y".into()), Token::Bold]),
+        (vec![Token::Text("z".into())], vec![Token::Text("w".into())]),
+      ]),
+      BlockLevelAttribute::Inline(vec![Token::Text("b".into())]),
+    ]);
+  }
+
+  #[test]
+  fn test_line_without_separator() {
+    assert_eq!(parse_definition_list(&mut tokenizer::tokenize(": x : y\n: z")), vec![
+      BlockLevelAttribute::DefinitionList(vec![
+        (vec![Token::Text("x".into())], vec![Token::Text("y".into())]),
+      ]),
+      BlockLevelAttribute::Inline(vec![Token::Text(": ".into()), Token::Text("z".into())]),
+    ]);
+  }
+}
@@ -0,0 +1,72 @@
+use crate::tokenizer::Token;
+
+use super::BlockLevelAttribute;
+
+/// Splits heading lines out of the buffer. The other lines are passed to `parse_definition_list`.
+pub fn parse_heading<'a>(buf: &mut Vec<Token<'a>>) -> Vec<BlockLevelAttribute<'a>> {
+  let buf = std::mem::take(buf);
+
+  let mut res: Vec<BlockLevelAttribute> = vec![];
+  let mut now_buf: Vec<Token> = vec![];
+  let mut heading: Option<(std::num::NonZeroUsize, Vec<Token>)> = None;
+
+  for token in buf {
+    match token {
+      Token::Heading(level) if heading.is_none() => {
+        // 前までの要素を書き出す
+        while let Some(Token::NewLine) = now_buf.last() {
+          now_buf.pop();
+        }
+        res.append(&mut super::parse_definition_list::parse_definition_list(&mut now_buf));
+
+        heading = Some((level, vec![]));
+      }
+
+      Token::NewLine if heading.is_some() => {
+        let (level, children) = heading.take().unwrap();
+        res.push(BlockLevelAttribute::Heading { level, children });
+      }
+
+      _ => {
+        if let Some((_, children)) = &mut heading {
+          children.push(token);
+        } else {
+          now_buf.push(token);
+        }
+      }
+    }
+  }
+
+  if let Some((level, children)) = heading {
+    res.push(BlockLevelAttribute::Heading { level, children });
+  }
+  res.append(&mut super::parse_definition_list::parse_definition_list(&mut now_buf));
+
+  res
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::tokenizer;
+
+  fn nz(v: usize) -> std::num::NonZeroUsize {
+    std::num::NonZeroUsize::try_from(v).unwrap()
+  }
+
+  #[test]
+  fn test_heading_between_paragraphs() {
+    assert_eq!(parse_heading(&mut tokenizer::tokenize("a\n++ Title\nb")), vec![
+      BlockLevelAttribute::Inline(vec![Token::Text("a".into())]),
+      BlockLevelAttribute::Heading { level: nz(2), children: vec![Token::Text("Title".into())] },
+      BlockLevelAttribute::Inline(vec![Token::Text("b".into())]),
+    ]);
+  }
+
+  #[test]
+  fn test_heading_at_end() {
+    assert_eq!(parse_heading(&mut tokenizer::tokenize("+ **Title**")), vec![
+      BlockLevelAttribute::Heading { level: nz(1), children: vec![Token::Bold, Token::Text("Title".into()), Token::Bold] },
+    ]);
+  }
+}
@@ -1,4 +1,4 @@
-pub fn parse_table(buf: &mut Vec<crate::tokenizer::Token>) -> Vec<super::BlockLevelAttribute> {
+pub fn parse_table<'a>(buf: &mut Vec<crate::tokenizer::Token<'a>>) -> Vec<super::BlockLevelAttribute<'a>> {
   use crate::tokenizer::Token;
   let buf = std::mem::take(buf);
 
@@ -61,14 +61,14 @@ pub fn parse_table(buf: &mut Vec<crate::tokenizer::Token>) -> Vec<super::BlockLe
           if !now_buf.is_empty() {
             let v = now_buf.pop().unwrap();
             if let Token::Text(mut st) = v {
-              st.push_str(&txt);
+              st.to_mut().push_str(&txt);
               now_buf.push(Token::Text(st))
             } else {
               now_buf.push(v);
-              now_buf.push(Token::Text(txt));
+              now_buf.push(Token::Text(txt.into()));
             }
           } else {
-            now_buf.push(Token::Text(txt));
+            now_buf.push(Token::Text(txt.into()));
           }
         }
 
@@ -121,6 +121,7 @@ pub fn parse_table(buf: &mut Vec<crate::tokenizer::Token>) -> Vec<super::BlockLe
   res
 }
 
+#[cfg(test)]
 mod tests {
   use crate::{block::{BlockLevelAttribute, table_cell::{BlockCell, Style}}, tokenizer::Token};
   use super::*;
@@ -133,9 +134,9 @@ mod tests {
 
   #[test]
   fn test_short_text() {
-    assert_eq!(parse_table(&mut tokenizer::tokenize(String::from("Hello, World!"))), vec![
+    assert_eq!(parse_table(&mut tokenizer::tokenize("Hello, World!")), vec![
       BlockLevelAttribute::Inline(vec![
-        Token::Text(String::from("Hello, World!"))
+        Token::Text("Hello, World!".into())
       ])
     ])
   }
@@ -146,37 +147,37 @@ mod tests {
 
   #[test]
   fn test_table_single() {
-    assert_eq!(parse_table(&mut tokenizer::tokenize(String::from("b\n|| a || b || c ||\na"))), vec![
-      BlockLevelAttribute::Inline(vec![Token::Text(String::from("b"))]),
+    assert_eq!(parse_table(&mut tokenizer::tokenize("b\n|| a || b || c ||\na")), vec![
+      BlockLevelAttribute::Inline(vec![Token::Text("b".into())]),
       BlockLevelAttribute::Table(vec![
         vec![
-          BlockCell { val: vec![Token::Text(String::from(" a "))], style: None, spanning: nz(1) },
-          BlockCell { val: vec![Token::Text(String::from(" b "))], style: None, spanning: nz(1) },
-          BlockCell { val: vec![Token::Text(String::from(" c "))], style: None, spanning: nz(1) },
+          BlockCell { val: vec![Token::Text(" a ".into())], style: None, spanning: nz(1) },
+          BlockCell { val: vec![Token::Text(" b ".into())], style: None, spanning: nz(1) },
+          BlockCell { val: vec![Token::Text(" c ".into())], style: None, spanning: nz(1) },
         ]
       ]),
-      BlockLevelAttribute::Inline(vec![Token::Text(String::from("a"))]),
+      BlockLevelAttribute::Inline(vec![Token::Text("a".into())]),
     ])
   }
 
   #[test]
   fn test_table_multi() {
-    println!("debug: {:?}", tokenizer::tokenize(String::from("b\n||~ a ||~ b ||~ c ||  \n||< d ||> e||=f ||\ng")));
-    assert_eq!(parse_table(&mut tokenizer::tokenize(String::from("b\n||~ a ||~ b ||~ c ||  \n||< d ||> e||=f ||\ng"))), vec![
-      BlockLevelAttribute::Inline(vec![Token::Text(String::from("b"))]),
+    println!("debug: {:?}", tokenizer::tokenize("b\n||~ a ||~ b ||~ c ||  \n||< d ||> e||=f ||\ng"));
+    assert_eq!(parse_table(&mut tokenizer::tokenize("b\n||~ a ||~ b ||~ c ||  \n||< d ||> e||=f ||\ng")), vec![
+      BlockLevelAttribute::Inline(vec![Token::Text("b".into())]),
       BlockLevelAttribute::Table(vec![
         vec![
-          BlockCell { val: vec![Token::Text(String::from(" a "))], style: Some(Style::Title), spanning: nz(1) },
-          BlockCell { val: vec![Token::Text(String::from(" b "))], style: Some(Style::Title), spanning: nz(1) },
-          BlockCell { val: vec![Token::Text(String::from(" c "))], style: Some(Style::Title), spanning: nz(1) },
+          BlockCell { val: vec![Token::Text(" a ".into())], style: Some(Style::Title), spanning: nz(1) },
+          BlockCell { val: vec![Token::Text(" b ".into())], style: Some(Style::Title), spanning: nz(1) },
+          BlockCell { val: vec![Token::Text(" c ".into())], style: Some(Style::Title), spanning: nz(1) },
         ],
         vec![
-          BlockCell { val: vec![Token::Text(String::from(" d "))], style: Some(Style::LeftAligned), spanning: nz(1) },
-          BlockCell { val: vec![Token::Text(String::from(" e"))], style: Some(Style::RightAligned), spanning: nz(1) },
-          BlockCell { val: vec![Token::Text(String::from("f "))], style: Some(Style::CenterAligned), spanning: nz(1) },
+          BlockCell { val: vec![Token::Text(" d ".into())], style: Some(Style::LeftAligned), spanning: nz(1) },
+          BlockCell { val: vec![Token::Text(" e".into())], style: Some(Style::RightAligned), spanning: nz(1) },
+          BlockCell { val: vec![Token::Text("f ".into())], style: Some(Style::CenterAligned), spanning: nz(1) },
         ]
       ]),
-      BlockLevelAttribute::Inline(vec![Token::Text(String::from("g"))]),
+      BlockLevelAttribute::Inline(vec![Token::Text("g".into())]),
     ])
   }
 }
\ No newline at end of file
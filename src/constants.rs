@@ -1,6 +1,9 @@
-pub mod collapsible {
-  pub const SHOW_BLOCK_DEFAULT_STRING: &str = "+ show block";
-  pub const HIDE_BLOCK_DEFAULT_STRING: &str = "- hide block";
-}
-
 pub const FOOTNOTE_ID_PREFIX: &str = "footnote-";
+pub const COLLAPSIBLE_ID_PREFIX: &str = "collapsible-";
+pub const TABVIEW_ID_PREFIX: &str = "wiki-tab-";
+pub const HEADING_ID_PREFIX: &str = "toc";
+pub const TOC_ID: &str = "toc";
+/// Upper limit of `[[lines n]]`, so that a page cannot produce a huge output.
+pub const MAX_LINES: usize = 100;
+/// Upper limit of `colspan` and `rowspan` of `[[cell]]`. Browsers clamp larger values anyway.
+pub const MAX_TABLE_SPAN: std::num::NonZeroUsize = std::num::NonZeroUsize::new(1000).unwrap();
@@ -0,0 +1,28 @@
+/// A problem found in the source which did not stop the parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+  /// An attribute or argument was written more than once in `[[...]]`. Only the first one is used.
+  /// `position` is the byte offset of the `[[` in the LF-normalized source.
+  DuplicateAttribute { element: String, name: String, position: usize },
+  /// `[[# name]]` uses a name which is already used by another anchor or a heading id (`toc0`, `toc1`, ...).
+  /// `position` is the byte offset of the `[[` in the LF-normalized source.
+  DuplicateAnchor { name: String, position: usize },
+  /// `[[module name]]` has no handler in `Options::modules`, so it was left out of the output.
+  /// `position` is the byte offset of the `[[` in the LF-normalized source.
+  UnknownModule { name: String, position: usize },
+  /// `[[include page]]` is not supported, so it was left out of the output.
+  /// `position` is the byte offset of the `[[` in the LF-normalized source.
+  UnsupportedInclude { page: String, position: usize },
+}
+
+impl Diagnostic {
+  /// Byte offset of the problem in the source.
+  pub(crate) fn position_mut(&mut self) -> &mut usize {
+    match self {
+      Self::DuplicateAttribute { position, .. }
+      | Self::DuplicateAnchor { position, .. }
+      | Self::UnknownModule { position, .. }
+      | Self::UnsupportedInclude { position, .. } => position,
+    }
+  }
+}
@@ -0,0 +1,307 @@
+//! Pull-parser API. The page is read as a flat stream of events instead of a tree,
+//! so callers can filter or map them, or render without keeping the whole page.
+
+use std::collections::VecDeque;
+use std::num::NonZeroUsize;
+
+use crate::ast::TreeElement;
+use crate::block::BlockParser;
+use crate::inline::InlineParser;
+use crate::options::Options;
+use crate::tokenizer::Tokenizer;
+
+pub use crate::ast::{Alignment, HideLocation};
+pub use crate::tokenizer::CellStyle;
+pub use crate::url::Url;
+
+/// An element with children. Its children are the events between `Event::Start` and the matching `Event::End`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tag {
+  Paragraph,
+  Bold,
+  Italics,
+  Underline,
+  Strikethrough,
+  Monospaced,
+  Superscript,
+  Subscript,
+  Colored { red: u8, green: u8, blue: u8 },
+  /// The value is a valid CSS size.
+  Size(String),
+  Collapsible { text_open: String, text_closed: String, folded: bool, hide_location: HideLocation },
+  BlockQuote,
+  /// Only holds `Tab`s.
+  TabView,
+  Tab(String),
+  /// `[[=]]`, `[[<]]`, `[[>]]` and `[[==]]` blocks.
+  Align(Alignment),
+  Heading(NonZeroUsize),
+  /// Attributes of tables, rows and cells are already sanitized.
+  Table { attributes: Vec<(String, String)> },
+  TableRow { attributes: Vec<(String, String)> },
+  /// `style` is `CellStyle::Title` for header cells.
+  TableCell { style: Option<CellStyle>, colspan: NonZeroUsize, rowspan: NonZeroUsize, attributes: Vec<(String, String)> },
+  /// Only holds `DefinitionTerm`s and `DefinitionDescription`s, in pairs.
+  DefinitionList,
+  DefinitionTerm,
+  DefinitionDescription,
+  /// The footnotes of the page. Comes after the last block and only holds `FootnoteDefinition`s.
+  FootnoteBlock,
+  FootnoteDefinition(NonZeroUsize),
+  /// `missing` is true if the linked page does not exist.
+  Link { href: Url, open_in_new_tab: bool, missing: bool },
+  /// Attributes are already sanitized. Tags not allowed by `Options::sanitize` are left out, keeping their children.
+  HtmlElement { tag: String, attributes: Vec<(String, String)> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+  Start(Tag),
+  End(Tag),
+  Text(String),
+  LineBreak,
+  FootnoteReference(NonZeroUsize),
+  /// Target of in-page links, `[[# name]]`.
+  Anchor(String),
+  Image { src: Url, alt: String },
+  /// Raw HTML shown in an iframe.
+  Iframe(String),
+  ExternalIframe { src: Url },
+  TableOfContents,
+  HorizontalRule,
+  /// Sanitized CSS of `[[module CSS]]`. It applies to the whole page, wherever it is written.
+  Stylesheet(String),
+  /// Rendered by the application, e.g. with the handlers in `Options::modules`.
+  Module(crate::module::Module),
+}
+
+/// Iterator over the events of a page. Blocks are parsed as the events are read.
+pub struct Events<'a> {
+  blocks: BlockParser<'a, Tokenizer<'a>>,
+  inline: Option<InlineParser>,
+  options: &'a Options,
+  pending: VecDeque<Event>,
+}
+
+impl<'a> Events<'a> {
+  /// `s` must use LF line endings.
+  pub fn new(s: &'a str, options: &'a Options) -> Self {
+    Self {
+      blocks: BlockParser::new(Tokenizer::new(s)),
+      inline: Some(InlineParser::new(options)),
+      options,
+      pending: VecDeque::new(),
+    }
+  }
+
+  fn push_element(&mut self, element: TreeElement) {
+    enum Item {
+      Element(TreeElement),
+      Event(Event),
+    }
+
+    let mut stack = vec![Item::Element(element)];
+    while let Some(item) = stack.pop() {
+      let element = match item {
+        Item::Element(element) => element,
+        Item::Event(event) => {
+          self.pending.push_back(event);
+          continue;
+        }
+      };
+
+      let (tag, children) = match element {
+        TreeElement::Text(text) => { self.pending.push_back(Event::Text(text)); continue; }
+        TreeElement::NewLine => { self.pending.push_back(Event::LineBreak); continue; }
+        TreeElement::Footnote(id) => { self.pending.push_back(Event::FootnoteReference(id)); continue; }
+        TreeElement::Anchor(name) => { self.pending.push_back(Event::Anchor(name)); continue; }
+        TreeElement::Image { src, alt } => { self.pending.push_back(Event::Image { src, alt }); continue; }
+        TreeElement::Iframe(html) => { self.pending.push_back(Event::Iframe(html)); continue; }
+        TreeElement::ExternalIframe { src } => { self.pending.push_back(Event::ExternalIframe { src }); continue; }
+        TreeElement::TableOfContents => { self.pending.push_back(Event::TableOfContents); continue; }
+        TreeElement::HorizontalRule => { self.pending.push_back(Event::HorizontalRule); continue; }
+        TreeElement::Stylesheet(css) => { self.pending.push_back(Event::Stylesheet(css)); continue; }
+        TreeElement::Module(module) => { self.pending.push_back(Event::Module(module)); continue; }
+
+        TreeElement::DefinitionList(items) => {
+          self.pending.push_back(Event::Start(Tag::DefinitionList));
+          stack.push(Item::Event(Event::End(Tag::DefinitionList)));
+          for (term, definition) in items.into_iter().rev() {
+            for (tag, children) in [(Tag::DefinitionDescription, definition), (Tag::DefinitionTerm, term)] {
+              stack.push(Item::Event(Event::End(tag.clone())));
+              stack.extend(children.into_iter().rev().map(Item::Element));
+              stack.push(Item::Event(Event::Start(tag)));
+            }
+          }
+          continue;
+        }
+
+        TreeElement::HtmlElement { tag, property, children } => {
+          if !self.options.sanitize.is_tag_allowed(&tag) {
+            stack.extend(children.into_iter().rev().map(Item::Element));
+            continue;
+          }
+          (Tag::HtmlElement { tag, attributes: property }, children)
+        }
+
+        TreeElement::Paragraph(children) => (Tag::Paragraph, children),
+        TreeElement::Bold(children) => (Tag::Bold, children),
+        TreeElement::Italics(children) => (Tag::Italics, children),
+        TreeElement::Underline(children) => (Tag::Underline, children),
+        TreeElement::Strikethrough(children) => (Tag::Strikethrough, children),
+        TreeElement::Monospaced(children) => (Tag::Monospaced, children),
+        TreeElement::Superscript(children) => (Tag::Superscript, children),
+        TreeElement::Subscript(children) => (Tag::Subscript, children),
+        TreeElement::Colored { red, green, blue, children } => (Tag::Colored { red, green, blue }, children),
+        TreeElement::Size { scale, children } => (Tag::Size(scale.into()), children),
+        TreeElement::Collapsible { text_open, text_closed, folded, hide_location, children } => (Tag::Collapsible { text_open, text_closed, folded, hide_location }, children),
+        TreeElement::FootnoteTarget(children) => (Tag::FootnoteBlock, children),
+        TreeElement::FootnoteTargetChild { id, children } => (Tag::FootnoteDefinition(id), children),
+        TreeElement::QuoteBlock(children) => (Tag::BlockQuote, children),
+        TreeElement::Tab { title, children } => (Tag::Tab(title), children),
+        TreeElement::TabView(children) => (Tag::TabView, children),
+        TreeElement::Align { alignment, children } => (Tag::Align(alignment), children),
+        TreeElement::Heading { level, children } => (Tag::Heading(level), children),
+        TreeElement::Table { attributes, children } => (Tag::Table { attributes }, children),
+        TreeElement::TableRow { attributes, children } => (Tag::TableRow { attributes }, children),
+        TreeElement::TableCell { style, colspan, rowspan, attributes, children } => (Tag::TableCell { style, colspan, rowspan, attributes }, children),
+        TreeElement::Link { href, open_in_new_tab, missing, children } => (Tag::Link { href, open_in_new_tab, missing }, children),
+      };
+
+      self.pending.push_back(Event::Start(tag.clone()));
+      stack.push(Item::Event(Event::End(tag)));
+      stack.extend(children.into_iter().rev().map(Item::Element));
+    }
+  }
+}
+
+impl Iterator for Events<'_> {
+  type Item = Event;
+
+  fn next(&mut self) -> Option<Event> {
+    loop {
+      if let Some(event) = self.pending.pop_front() {
+        return Some(event);
+      }
+
+      // 脚注ブロックは最後のブロックの後に出す
+      let inline = self.inline.as_mut()?;
+      let elements = match self.blocks.next() {
+        Some(block) => inline.parse_block(block),
+        None => self.inline.take().and_then(InlineParser::finish).into_iter().collect(),
+      };
+      for element in elements {
+        self.push_element(element);
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn nz(v: usize) -> NonZeroUsize {
+    NonZeroUsize::try_from(v).unwrap()
+  }
+
+  fn events(s: &str) -> Vec<Event> {
+    let options = Options::default();
+    Events::new(s, &options).collect()
+  }
+
+  #[test]
+  fn test_text() {
+    assert_eq!(events("Hello, **World**!"), vec![
+      Event::Start(Tag::Paragraph),
+      Event::Text(String::from("Hello, ")),
+      Event::Start(Tag::Bold),
+      Event::Text(String::from("World")),
+      Event::End(Tag::Bold),
+      Event::Text(String::from("!")),
+      Event::End(Tag::Paragraph),
+    ]);
+  }
+
+  #[test]
+  fn test_blocks() {
+    assert_eq!(events("+ Title\n> a\n> b"), vec![
+      Event::Start(Tag::Heading(nz(1))),
+      Event::Text(String::from("Title")),
+      Event::End(Tag::Heading(nz(1))),
+      Event::Start(Tag::BlockQuote),
+      Event::Start(Tag::Paragraph),
+      Event::Text(String::from("a")),
+      Event::LineBreak,
+      Event::Text(String::from("b")),
+      Event::End(Tag::Paragraph),
+      Event::End(Tag::BlockQuote),
+    ]);
+  }
+
+  #[test]
+  fn test_footnotes() {
+    assert_eq!(events("a[[footnote]]b[[/footnote]]\n\nc"), vec![
+      Event::Start(Tag::Paragraph),
+      Event::Text(String::from("a")),
+      Event::FootnoteReference(nz(1)),
+      Event::End(Tag::Paragraph),
+      Event::Start(Tag::Paragraph),
+      Event::Text(String::from("c")),
+      Event::End(Tag::Paragraph),
+      Event::Start(Tag::FootnoteBlock),
+      Event::Start(Tag::FootnoteDefinition(nz(1))),
+      Event::Text(String::from("b")),
+      Event::End(Tag::FootnoteDefinition(nz(1))),
+      Event::End(Tag::FootnoteBlock),
+    ]);
+  }
+
+  #[test]
+  fn test_definition_list() {
+    assert_eq!(events(": a : **b**"), vec![
+      Event::Start(Tag::DefinitionList),
+      Event::Start(Tag::DefinitionTerm),
+      Event::Text(String::from("a")),
+      Event::End(Tag::DefinitionTerm),
+      Event::Start(Tag::DefinitionDescription),
+      Event::Start(Tag::Bold),
+      Event::Text(String::from("b")),
+      Event::End(Tag::Bold),
+      Event::End(Tag::DefinitionDescription),
+      Event::End(Tag::DefinitionList),
+    ]);
+  }
+
+  #[test]
+  fn test_table() {
+    let cell = |style| Tag::TableCell { style, colspan: nz(1), rowspan: nz(1), attributes: vec![] };
+    let row = Tag::TableRow { attributes: vec![] };
+    assert_eq!(events("||~ a ||\n|| b ||"), vec![
+      Event::Start(Tag::Table { attributes: vec![] }),
+      Event::Start(row.clone()),
+      Event::Start(cell(Some(CellStyle::Title))),
+      Event::Text(String::from(" a ")),
+      Event::End(cell(Some(CellStyle::Title))),
+      Event::End(row.clone()),
+      Event::Start(row.clone()),
+      Event::Start(cell(None)),
+      Event::Text(String::from(" b ")),
+      Event::End(cell(None)),
+      Event::End(row),
+      Event::End(Tag::Table { attributes: vec![] }),
+    ]);
+  }
+
+  #[test]
+  fn test_filter() {
+    let options = Options::default();
+    let text: String = Events::new("a **b** //c//\n\nd", &options)
+      .filter_map(|event| match event {
+        Event::Text(text) => Some(text),
+        _ => None,
+      })
+      .collect();
+    assert_eq!(text, "a b cd");
+  }
+}
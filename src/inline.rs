@@ -8,14 +8,25 @@ pub struct ParseState {
   footnotes: Vec<Vec<TreeElement>>,
   reg_footnote_count: usize,
   out_footnote_count: usize,
+  in_link: bool, // リンクのラベルを解析中
+  options: crate::options::Options,
+  diagnostics: Vec<crate::diagnostic::Diagnostic>,
 }
 
 impl ParseState {
+  #[cfg(test)]
   pub fn new() -> Self {
+    Self::with_options(crate::options::Options::default())
+  }
+
+  pub fn with_options(options: crate::options::Options) -> Self {
     Self {
       footnotes: vec![],
       reg_footnote_count: 0,
       out_footnote_count: 0,
+      in_link: false,
+      options,
+      diagnostics: vec![],
     }
   }
 
@@ -26,7 +37,7 @@ impl ParseState {
   }
 
   pub fn edit_footnote(&mut self, elements: Vec<crate::ast::TreeElement>) {
-    if let Some(_) = self.footnotes.pop() {
+    if self.footnotes.pop().is_some() {
       self.footnotes.push(elements);
     }
   }
@@ -46,60 +57,159 @@ impl ParseState {
   }
 }
 
-pub fn parse(block_tree: Vec<crate::block::BlockLevelAttribute>) -> Vec<crate::ast::TreeElement> {
-  let mut db = DataBuilder::new();
-  let mut shared = ParseState::new();
+/// Inline-parses blocks one top-level block at a time. Footnotes are numbered across the blocks.
+pub struct InlineParser {
+  shared: ParseState,
+}
 
-  let mut iters = vec![block_tree.into_iter()];
+impl InlineParser {
+  pub fn new(options: &crate::options::Options) -> Self {
+    Self { shared: ParseState::with_options(options.clone()) }
+  }
 
-  while !iters.is_empty() {
-    if let Some(block) = iters.last_mut().unwrap().next() {
-      match block {
-        BlockLevelAttribute::BlockQuote(children) => {
-          db.push(ParseFrame::QuoteBlock);
-          iters.push(children.into_iter());
-        }
+  /// Elements made from one top-level block.
+  pub fn parse_block(&mut self, block: BlockLevelAttribute<'_>) -> Vec<TreeElement> {
+    let shared = &mut self.shared;
+    let mut db = DataBuilder::new();
+    let mut iters: Vec<std::vec::IntoIter<BlockLevelAttribute>> = vec![vec![block].into_iter()];
+
+    while let Some(iter) = iters.last_mut() {
+      if let Some(block) = iter.next() {
+        match block {
+          BlockLevelAttribute::BlockQuote(children) => {
+            db.push(ParseFrame::QuoteBlock);
+            iters.push(children.into_iter());
+          }
 
-        BlockLevelAttribute::TabView(children) => {
-          db.push(ParseFrame::TabView);
-          iters.push(children.into_iter());
-        }
+          BlockLevelAttribute::TabView(children) => {
+            db.push(ParseFrame::TabView);
+            iters.push(children.into_iter());
+          }
+
+          BlockLevelAttribute::Align { alignment, children } => {
+            db.push(ParseFrame::Align(alignment));
+            iters.push(children.into_iter());
+          }
+
+          BlockLevelAttribute::Table(table) => {
+            let one = std::num::NonZeroUsize::MIN;
+            let rows = table.into_iter().map(|row| {
+              let cells = row.into_iter().map(|cell| TreeElement::TableCell {
+                style: cell.style,
+                colspan: cell.spanning,
+                rowspan: one,
+                attributes: vec![],
+                children: parse_inline::parse_inline(cell.val, shared),
+              }).collect();
+              TreeElement::TableRow { attributes: vec![], children: cells }
+            }).collect();
+
+            db.add(TreeElement::Table { attributes: vec![], children: rows });
+          }
+
+          BlockLevelAttribute::AdvancedTable { attributes, children } => {
+            db.push(ParseFrame::Table { attributes: shared.options.sanitize.sanitize_attributes("table", &attributes) });
+            iters.push(children.into_iter());
+          }
+
+          BlockLevelAttribute::TableRow { attributes, children } => {
+            db.push(ParseFrame::TableRow { attributes: shared.options.sanitize.sanitize_attributes("tr", &attributes) });
+            iters.push(children.into_iter());
+          }
+
+          BlockLevelAttribute::TableCell { header, attributes, children } => {
+            let span = |key: &str| attributes.iter()
+              .find(|(k, _)| k.eq_ignore_ascii_case(key))
+              .and_then(|(_, value)| value.trim().parse::<std::num::NonZeroUsize>().ok())
+              .map_or(std::num::NonZeroUsize::MIN, |v| v.min(crate::constants::MAX_TABLE_SPAN));
+            db.push(ParseFrame::TableCell {
+              style: header.then_some(crate::ast::table_cell::Style::Title),
+              colspan: span("colspan"),
+              rowspan: span("rowspan"),
+              attributes: shared.options.sanitize.sanitize_attributes(if header { "th" } else { "td" }, &attributes),
+            });
+            iters.push(children.into_iter());
+          }
+
+          BlockLevelAttribute::Tab { title, children } => {
+            db.push(ParseFrame::Tab(title));
+            iters.push(children.into_iter());
+          }
 
-        BlockLevelAttribute::Table(table) => {
-          let mut res = vec![vec![]];
+          BlockLevelAttribute::Heading { level, children } => {
+            db.add(TreeElement::Heading { level, children: parse_inline::parse_inline(children, shared) });
+          }
+
+          BlockLevelAttribute::TableOfContents => {
+            db.add(TreeElement::TableOfContents);
+          }
+
+          BlockLevelAttribute::HorizontalRule => {
+            db.add(TreeElement::HorizontalRule);
+          }
 
-          for vc in table {
-            for item in vc {
-              res.last_mut().unwrap().push(crate::ast::table_cell::Cell {
-                val: parse_inline::parse_inline(item.val, &mut shared),
-                style: item.style,
-                spanning: item.spanning,
-              })
+          BlockLevelAttribute::Stylesheet(css) => {
+            if let Some(css) = shared.options.sanitize.sanitize_stylesheet(&css) {
+              db.add(TreeElement::Stylesheet(css));
             }
           }
 
-          db.add(TreeElement::Table(res));
-        }
+          BlockLevelAttribute::Module { name, args, body, position } => {
+            if !shared.options.modules.contains(&name) {
+              shared.diagnostics.push(crate::diagnostic::Diagnostic::UnknownModule { name: name.to_string(), position });
+            }
+            db.add(TreeElement::Module(crate::module::Module {
+              name: name.into_owned(),
+              args: args.into_iter().map(|(key, value)| (key.into_owned(), value.into_owned())).collect(),
+              body: body.map(|v| v.into_owned()),
+            }));
+          }
 
-        BlockLevelAttribute::Tab { title, children } => {
-          db.push(ParseFrame::Tab(title));
-          iters.push(children.into_iter());
-        }
+          BlockLevelAttribute::DefinitionList(items) => {
+            let items = items.into_iter()
+              .map(|(term, definition)| (parse_inline::parse_inline(term, shared), parse_inline::parse_inline(definition, shared)))
+              .collect();
+            db.add(TreeElement::DefinitionList(items));
+          }
 
-        BlockLevelAttribute::Inline(children) => {
-          db.add(TreeElement::Paragraph(parse_inline::parse_inline(children, &mut shared)));
+          BlockLevelAttribute::Inline(children) => {
+            db.add(TreeElement::Paragraph(parse_inline::parse_inline(children, shared)));
+          }
         }
+      } else {
+        iters.pop();
+        db.pop_and_merge();
       }
-    } else {
-      db.pop_and_merge();
-      iters.pop();
     }
+
+    db.into()
+  }
+
+  /// Problems found since the last call, e.g. modules without a handler.
+  pub fn take_diagnostics(&mut self) -> Vec<crate::diagnostic::Diagnostic> {
+    std::mem::take(&mut self.shared.diagnostics)
   }
 
-  if let Some(v) = shared.insert_footnote_block() {
-    db.add(v);
+  /// The footnote block placed after the last block, if the page has footnotes.
+  pub fn finish(mut self) -> Option<TreeElement> {
+    self.shared.insert_footnote_block()
   }
+}
 
-  db.into()
+pub fn parse<'a>(block_tree: impl IntoIterator<Item = BlockLevelAttribute<'a>>, options: &crate::options::Options) -> Vec<crate::ast::TreeElement> {
+  parse_with_diagnostics(block_tree, options).0
 }
 
+pub fn parse_with_diagnostics<'a>(block_tree: impl IntoIterator<Item = BlockLevelAttribute<'a>>, options: &crate::options::Options) -> (Vec<crate::ast::TreeElement>, Vec<crate::diagnostic::Diagnostic>) {
+  let mut parser = InlineParser::new(options);
+
+  // トップレベルは読みながら処理する
+  let mut res = vec![];
+  for block in block_tree {
+    res.extend(parser.parse_block(block));
+  }
+  let diagnostics = parser.take_diagnostics();
+  res.extend(parser.finish());
+
+  (res, diagnostics)
+}
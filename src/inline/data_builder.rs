@@ -15,12 +15,11 @@ impl DataBuilder {
 
   pub fn pop_and_merge(&mut self) -> bool {
     if let Some((frame, elem)) = self.data.pop() {
-      let push_target: &mut Vec<TreeElement>;
-      if self.data.is_empty() {
-        push_target = &mut self.root;
+      let push_target: &mut Vec<TreeElement> = if self.data.is_empty() {
+        &mut self.root
       } else {
-        push_target = &mut self.data.last_mut().unwrap().1;
-      }
+        &mut self.data.last_mut().unwrap().1
+      };
 
       push_target.push(frame.into_tree_element(elem));
 
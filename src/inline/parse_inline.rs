@@ -5,12 +5,142 @@ use crate::constants;
 use crate::inline::tags::TagKind;
 use crate::tokenizer::Token;
 
-pub fn parse_inline(tokens: Vec<crate::tokenizer::Token>, state: &mut super::ParseState) -> Vec<crate::ast::TreeElement> {
-  let mut db = inline_builder::InlineBuilder::new();
+/// Source text of a marker token, used when the marker has no partner
+/// or when a block-level marker ends up inside a line (e.g. `||` in a heading).
+fn marker_text(token: &Token) -> Option<String> {
+  match token {
+    Token::Bold => Some(String::from("**")),
+    Token::Italics => Some(String::from("//")),
+    Token::Underline => Some(String::from("__")),
+    Token::Strikethrough => Some(String::from("--")),
+    Token::Superscript => Some(String::from("^^")),
+    Token::Subscript => Some(String::from(",,")),
+    Token::MonospacedOpen => Some(String::from("{{")),
+    Token::MonospacedClose => Some(String::from("}}")),
+    Token::ColoredBeginColorCode(code) => Some(format!("##{}|", code)),
+    Token::ColoredBeginColorName(name) => Some(format!("##{}|", name)),
+    Token::ColoredEnd => Some(String::from("##")),
+    Token::BlockQuote(level) => Some(format!("{} ", ">".repeat(level.get()))),
+    Token::Heading(level) => Some(format!("{} ", "+".repeat(level.get()))),
+    Token::HorizontalRule => Some(String::from("----")),
+    Token::CellSeparator(style) => Some(match style {
+      None => String::from("||"),
+      Some(ast::table_cell::Style::Title) => String::from("||~"),
+      Some(ast::table_cell::Style::LeftAligned) => String::from("||<"),
+      Some(ast::table_cell::Style::RightAligned) => String::from("||>"),
+      Some(ast::table_cell::Style::CenterAligned) => String::from("||="),
+    }),
+    _ => None,
+  }
+}
 
-  for token in tokens {
-    if let Ok(frame) = token.clone().try_into() {
-      db.switch_element(frame);
+/// Whether the token is whitespace at the given side. The ends of the paragraph count as whitespace.
+fn is_whitespace_side(token: Option<&Token>, at_start: bool) -> bool {
+  match token {
+    None | Some(Token::NewLine) | Some(Token::LineBreak) => true,
+    Some(Token::Text(text)) => {
+      if at_start {
+        text.starts_with(char::is_whitespace)
+      } else {
+        text.ends_with(char::is_whitespace)
+      }
+    }
+    Some(_) => false,
+  }
+}
+
+/// Index of a toggle marker in the stacks of `literalize_unmatched`.
+fn toggle_index(token: &Token) -> Option<usize> {
+  match token {
+    Token::Bold => Some(0),
+    Token::Italics => Some(1),
+    Token::Underline => Some(2),
+    Token::Strikethrough => Some(3),
+    Token::Superscript => Some(4),
+    Token::Subscript => Some(5),
+    _ => None,
+  }
+}
+
+/// Turns markers without a partner into text, as Wikidot prints them literally.
+/// A toggle marker can only open if it is not followed by whitespace, and only close if it does not follow whitespace.
+/// Returns the positions of the toggle markers which close, in order. The others open.
+fn literalize_unmatched(tokens: &mut [Token]) -> Vec<usize> {
+  let mut unmatched = vec![];
+  let mut closers = vec![];
+  let mut toggles: [Vec<usize>; 6] = Default::default(); // 開いているトグル記号 (種類ごと)
+  let mut monospaced = vec![];
+  let mut colored = vec![];
+
+  for (i, token) in tokens.iter().enumerate() {
+    if let Some(kind) = toggle_index(token) {
+      let can_open = !is_whitespace_side(tokens.get(i+1), true);
+      let can_close = !is_whitespace_side(i.checked_sub(1).and_then(|v| tokens.get(v)), false);
+
+      if can_close && toggles[kind].pop().is_some() {
+        closers.push(i);
+      } else if can_open {
+        toggles[kind].push(i);
+      } else {
+        unmatched.push(i);
+      }
+      continue;
+    }
+
+    match token {
+      Token::MonospacedOpen => { monospaced.push(i); }
+      Token::MonospacedClose if monospaced.pop().is_none() => { unmatched.push(i); }
+
+      Token::ColoredBeginColorCode(_) | Token::ColoredBeginColorName(_) => { colored.push(i); }
+      Token::ColoredEnd if colored.pop().is_none() => { unmatched.push(i); }
+
+      _ => {}
+    }
+  }
+
+  unmatched.extend(toggles.into_iter().flatten());
+  unmatched.extend(monospaced);
+  unmatched.extend(colored);
+
+  for i in unmatched {
+    if let Some(text) = marker_text(&tokens[i]) {
+      tokens[i] = Token::Text(text.into());
+    }
+  }
+
+  closers
+}
+
+/// Whether a link is being built. Links cannot be nested.
+fn is_in_link(db: &inline_builder::InlineBuilder, state: &super::ParseState) -> bool {
+  state.in_link || db.is_inside(&ast::ParseFrameKind::HtmlElement { tag: String::from("a") })
+}
+
+/// Parses a link label as wikitext. Links in the label are replaced by their labels.
+/// Problems in the label were already reported by the tokenizer which found the link.
+fn parse_label(label: &str, state: &mut super::ParseState) -> Vec<ast::TreeElement> {
+  let tokens = crate::tokenizer::Tokenizer::new_inline(label).collect();
+  let in_link = std::mem::replace(&mut state.in_link, true);
+  let res = parse_inline(tokens, state);
+  state.in_link = in_link;
+  res
+}
+
+pub fn parse_inline(mut tokens: Vec<crate::tokenizer::Token<'_>>, state: &mut super::ParseState) -> Vec<crate::ast::TreeElement> {
+  let mut db = inline_builder::InlineBuilder::new();
+  let mut ignored_links: usize = 0; // リンク中の [[a]] の数
+  let closers = literalize_unmatched(&mut tokens);
+
+  for (i, token) in tokens.into_iter().enumerate() {
+    if let Ok(frame) = ast::ParseFrame::try_from(token.clone()) {
+      // 閉じる記号は同じ種類の一番内側の要素を閉じる
+      if closers.binary_search(&i).is_err() {
+        db.push(frame);
+      } else if db.is_inside(&frame.get_kind()) {
+        db.close_element(frame.get_kind());
+      } else {
+        db.add(ast::TreeElement::Text(marker_text(&token).unwrap_or_default()));
+      }
       continue;
     } else {
       match token {
@@ -26,66 +156,71 @@ pub fn parse_inline(tokens: Vec<crate::tokenizer::Token>, state: &mut super::Par
           db.close_element(ast::ParseFrameKind::Monospaced);
         }
 
-        Token::ElementBegin { name, attributes } => {
+        Token::ElementBegin { name, attributes, .. } => {
           if let Some(e) = crate::inline::tags::get_tag_kind_from_str(&name) {
-            let mut properties: Vec<(&str, &str)> = vec![];
             let mut unnnamed_properties = vec![];
             for (key, value) in &attributes {
-              match key.as_str() {
-                "style" | "class" => { properties.push((key, value)); }
-                "" => { unnnamed_properties.push(value); }
-                &_ => {}
+              if key.is_empty() {
+                unnnamed_properties.push(value);
               }
             }
 
             match e {
               TagKind::Span => {
-                let properties_owned = properties.iter().map(|(a, b)| { (a.to_string(), b.to_string()) }).collect();
-                db.push(ast::ParseFrame::HtmlElement { tag: "span".to_string(), properties: properties_owned });
+                let properties = state.options.sanitize.sanitize_attributes("span", &attributes);
+                db.push(ast::ParseFrame::HtmlElement { tag: "span".to_string(), properties });
               }
 
               TagKind::Size => {
-                if unnnamed_properties.len() >= 1 {
-                  let arg = unnnamed_properties[0].to_lowercase();
-                  let arg = arg.trim();
-                  db.push(ast::ParseFrame::Size { scale: CssSize::new(arg) });
-                } else {
-                  db.push(ast::ParseFrame::Size { scale: CssSize::new("1em") });
+                let arg = unnnamed_properties.first().map(|v| v.to_lowercase());
+                match arg {
+                  Some(arg) if state.options.sanitize.is_css_value_allowed("font-size", arg.trim()) => {
+                    db.push(ast::ParseFrame::Size { scale: CssSize::new(arg.trim()) });
+                  }
+                  _ => {
+                    db.push(ast::ParseFrame::Size { scale: CssSize::new("1em") });
+                  }
                 }
               }
 
+              TagKind::Link if is_in_link(&db, state) => {
+                // リンクの入れ子は作らない
+                ignored_links += 1;
+              }
+
               TagKind::Link => {
-                for (key, value) in &attributes {
-                  match key.as_str() {
-                    "href" => { properties.push((key, value)); }
-                    &_ => {}
+                let properties = state.options.sanitize.sanitize_attributes("a", &attributes).into_iter().filter_map(|(key, value)| {
+                  if key == "href" {
+                    ast::Url::parse(&value, &state.options.url).ok().map(|url| (key, String::from(url)))
+                  } else {
+                    Some((key, value))
                   }
-                }
-
-                let properties = properties.iter().map(|(a,b)| { (a.to_string(), b.to_string()) }).collect();
+                }).collect();
                 db.push(ast::ParseFrame::HtmlElement { tag: "a".to_string(), properties });
               }
 
               TagKind::Collapsible => {
-                let mut show_str = constants::collapsible::SHOW_BLOCK_DEFAULT_STRING;
-                let mut hide_str = constants::collapsible::HIDE_BLOCK_DEFAULT_STRING;
+                let mut show_str = state.options.locale.collapsible_show.as_str();
+                let mut hide_str = state.options.locale.collapsible_hide.as_str();
+                let mut folded = true;
+                let mut hide_location = ast::HideLocation::Top;
 
                 for (key, value) in &attributes {
-                  match key.as_str() {
-                    "show" => { show_str = &value; }
-                    "hide" => { hide_str = &value; }
+                  match key.to_lowercase().as_str() {
+                    "show" => { show_str = value; }
+                    "hide" => { hide_str = value; }
+                    "folded" => { folded = !value.trim().eq_ignore_ascii_case("no"); }
+                    "hidelocation" => { hide_location = ast::HideLocation::from(value).unwrap_or(hide_location); }
                     &_ => {}
                   }
                 }
 
-                db.push(ast::ParseFrame::Collapsible { text_open: show_str.to_string(), text_closed: hide_str.to_string() });
+                db.push(ast::ParseFrame::Collapsible { text_open: show_str.to_string(), text_closed: hide_str.to_string(), folded, hide_location });
               }
 
               TagKind::Footnote => {
                 let footnote_id = state.register_footnote();
-                db.push(ast::ParseFrame::Superscript);
-                  db.add(ast::TreeElement::Link { href: ast::Url(format!("#{}{}", constants::FOOTNOTE_ID_PREFIX, footnote_id)), open_in_new_tab: false, name: footnote_id.to_string() });
-                db.pop_and_merge();
+                db.add(ast::TreeElement::Footnote(footnote_id));
                 db.push(ast::ParseFrame::Footnote(footnote_id));
               }
 
@@ -96,13 +231,43 @@ pub fn parse_inline(tokens: Vec<crate::tokenizer::Token>, state: &mut super::Par
               }
 
               TagKind::Include => {
-                todo!();
+                // 他のページは読み込めないので書かない (Diagnostic::UnsupportedInclude)
               }
 
               TagKind::Div => {
-                let properties = properties.iter().map(|(a,b)| { (a.to_string(), b.to_string()) }).collect();
+                let properties = state.options.sanitize.sanitize_attributes("div", &attributes);
                 db.push(ast::ParseFrame::HtmlElement { tag: "div".to_string(), properties });
               }
+
+              TagKind::Image => {
+                let alt = attributes.iter().find(|(key, _)| key == "alt").map(|(_, value)| value.to_string()).unwrap_or_default();
+                if let Some(source) = unnnamed_properties.first() && let Ok(src) = ast::Url::parse(source, &state.options.url) {
+                  db.add(ast::TreeElement::Image { src, alt });
+                }
+              }
+
+              TagKind::Iframe => {
+                if let Some(source) = unnnamed_properties.first() && let Ok(src) = ast::Url::parse(source, &state.options.url) {
+                  db.add(ast::TreeElement::ExternalIframe { src });
+                }
+              }
+
+              TagKind::Anchor => {
+                if let Some(name) = unnnamed_properties.first() && !name.trim().is_empty() {
+                  db.add(ast::TreeElement::Anchor(name.trim().to_string()));
+                }
+              }
+
+              TagKind::LineBreak => {
+                db.add(ast::TreeElement::NewLine);
+              }
+
+              TagKind::Lines => {
+                let count = unnnamed_properties.first().and_then(|v| v.trim().parse::<usize>().ok()).unwrap_or(1);
+                for _ in 0..count.min(constants::MAX_LINES) {
+                  db.add(ast::TreeElement::NewLine);
+                }
+              }
             }
           } else {
             // ignore
@@ -112,6 +277,10 @@ pub fn parse_inline(tokens: Vec<crate::tokenizer::Token>, state: &mut super::Par
         Token::ElementEnd(name) => {
           if let Some(e) = crate::inline::tags::get_tag_kind_from_str(&name) {
             match e {
+              TagKind::Link if ignored_links > 0 => {
+                ignored_links -= 1;
+              }
+
               TagKind::Span | TagKind::Size | TagKind::Link | TagKind::Collapsible | TagKind::Div => {
                 db.pop_and_merge();
               }
@@ -121,7 +290,7 @@ pub fn parse_inline(tokens: Vec<crate::tokenizer::Token>, state: &mut super::Par
                 state.edit_footnote(dat);
               }
 
-              TagKind::FootnoteTarget | TagKind::Include => {
+              TagKind::FootnoteTarget | TagKind::Include | TagKind::Image | TagKind::Iframe | TagKind::Anchor | TagKind::LineBreak | TagKind::Lines => {
                 // ignore
               }
             }
@@ -131,25 +300,10 @@ pub fn parse_inline(tokens: Vec<crate::tokenizer::Token>, state: &mut super::Par
         }
 
         Token::ColoredBeginColorName(name) => {
-          db.push(match name.as_str() {
-            "aqua"    => ast::ParseFrame::Colored { red: 0x00, green: 0xFF, blue: 0xFF },
-            "black"   => ast::ParseFrame::Colored { red: 0x00, green: 0x00, blue: 0x00 },
-            "blue"    => ast::ParseFrame::Colored { red: 0x00, green: 0x00, blue: 0xFF },
-            "fuchsia" => ast::ParseFrame::Colored { red: 0xFF, green: 0x00, blue: 0xFF }, // magenta
-            "grey"    => ast::ParseFrame::Colored { red: 0x80, green: 0x80, blue: 0x80 },
-            "green"   => ast::ParseFrame::Colored { red: 0x00, green: 0x80, blue: 0x00 },
-            "lime"    => ast::ParseFrame::Colored { red: 0x00, green: 0xFF, blue: 0x00 },
-            "maroon"  => ast::ParseFrame::Colored { red: 0x80, green: 0x00, blue: 0x00 },
-            "navy"    => ast::ParseFrame::Colored { red: 0x00, green: 0x00, blue: 0x80 },
-            "olive"   => ast::ParseFrame::Colored { red: 0x80, green: 0x80, blue: 0x00 },
-            "purple"  => ast::ParseFrame::Colored { red: 0x80, green: 0x00, blue: 0x80 },
-            "red"     => ast::ParseFrame::Colored { red: 0xFF, green: 0x00, blue: 0x00 },
-            "silver"  => ast::ParseFrame::Colored { red: 0xC0, green: 0xC0, blue: 0xC0 },
-            "teal"    => ast::ParseFrame::Colored { red: 0x00, green: 0x80, blue: 0x80 },
-            "white"   => ast::ParseFrame::Colored { red: 0xFF, green: 0xFF, blue: 0xFF },
-            "yellow"  => ast::ParseFrame::Colored { red: 0xFF, green: 0xFF, blue: 0x00 },
-            &_        => unreachable!(),
-          });
+          if let Some(color) = ast::WikidotColor::from(&name) {
+            let (red, green, blue) = color.rgb();
+            db.push(ast::ParseFrame::Colored { red, green, blue });
+          }
         }
 
         Token::ColoredBeginColorCode(code) => {
@@ -166,28 +320,77 @@ pub fn parse_inline(tokens: Vec<crate::tokenizer::Token>, state: &mut super::Par
           db.close_element(ast::ParseFrameKind::Colored);
         }
 
-        Token::NamedLink { link, name } => {
-          db.add(ast::TreeElement::Link { href: ast::Url(link), open_in_new_tab: false, name });
+        Token::NamedLink { link, name, new_tab, source } => {
+          // [#name text] は同じページのアンカーを指す
+          let href = match link.strip_prefix('#') {
+            Some(anchor) => Ok(ast::Url::fragment(&state.options.element_id(anchor))),
+            None => ast::Url::parse(&link, &state.options.url),
+          };
+          let Ok(href) = href else {
+            // 使えないURLは書かれたまま表示する
+            db.add(ast::TreeElement::Text(source.into_owned()));
+            continue;
+          };
+          // 地の文のURLはラベルを解析しない
+          let children = if name == link { vec![ast::TreeElement::Text(name.into_owned())] } else { parse_label(&name, state) };
+          if is_in_link(&db, state) {
+            children.into_iter().for_each(|v| db.add(v));
+          } else {
+            db.add(ast::TreeElement::Link { href, open_in_new_tab: new_tab, missing: false, children });
+          }
+        }
+
+        Token::PageLink { link, target, name, new_tab } => {
+          // 名前がなければページのタイトル, それもなければ書かれたままのリンク先を表示する
+          let resolver = state.options.link_resolver.as_ref();
+          let href = resolver.and_then(|resolver| resolver.url(&target)).unwrap_or_else(|| target.href(&state.options));
+          // アンカーだけのリンクは同じページを指す
+          let missing = !target.page.is_empty() && resolver.is_some_and(|resolver| !resolver.exists(&target));
+          let children = match name.trim() {
+            "" => vec![ast::TreeElement::Text(resolver.and_then(|resolver| resolver.title(&target)).unwrap_or_else(|| link.trim().to_string()))],
+            name => parse_label(name, state),
+          };
+          match ast::Url::parse(&href, &state.options.url) {
+            Ok(href) if !is_in_link(&db, state) => { db.add(ast::TreeElement::Link { href, open_in_new_tab: new_tab, missing, children }); }
+            _ => { children.into_iter().for_each(|v| db.add(v)); }
+          }
+        }
+
+        // ブロックとして読まれなかったもの (見出しの中の || など) は文字として扱う
+        Token::BlockQuote(_) | Token::Heading(_) | Token::HorizontalRule | Token::CellSeparator(_) => {
+          db.add(ast::TreeElement::Text(marker_text(&token).unwrap_or_default()));
         }
 
-        Token::PageLink { link, name } => {
-          db.add(ast::TreeElement::Link { href: ast::Url(format!("/{}", link)), open_in_new_tab: false, name });
+        // ブロックとして読まれなかったもの (リンクのラベルの中など) は無視する
+        Token::Stylesheet(_) => {}
+
+        // 行の途中のモジュールはその場に置く
+        Token::Module { name, args, body, position } => {
+          if !state.options.modules.contains(&name) {
+            state.diagnostics.push(crate::diagnostic::Diagnostic::UnknownModule { name: name.to_string(), position });
+          }
+          db.add(ast::TreeElement::Module(crate::module::Module {
+            name: name.into_owned(),
+            args: args.into_iter().map(|(key, value)| (key.into_owned(), value.into_owned())).collect(),
+            body: body.map(|v| v.into_owned()),
+          }));
         }
 
-        Token::BlockQuote(_) => {
-          unreachable!(); // already handled in block parsing
+        // 行頭以外に現れた場合 (" _" で行が続いたときなど) は文字として扱う
+        Token::DefinitionTerm => {
+          db.add(ast::TreeElement::Text(String::from(": ")));
         }
 
-        Token::CellSeparator(_) => {
-          unreachable!(); // already handled in block parsing
+        Token::DefinitionSeparator => {
+          db.add(ast::TreeElement::Text(String::from(" : ")));
         }
 
-        Token::NewLine => {
+        Token::NewLine | Token::LineBreak => {
           db.add(ast::TreeElement::NewLine);
         }
 
         Token::Text(text) => {
-          db.add(ast::TreeElement::Text(text));
+          db.add(ast::TreeElement::Text(text.into_owned()));
         }
       }
     }
@@ -209,22 +412,46 @@ mod tests {
     TreeElement::Text(s.to_string())
   }
 
+  fn url(s: &str) -> ast::Url {
+    ast::Url::parse(s, &crate::url::UrlPolicy::default()).unwrap()
+  }
+
+  fn page(s: &str) -> crate::link::PageLink {
+    crate::link::PageLink::parse(s).unwrap()
+  }
+
   #[test]
   fn test_plain_text() {
     let mut state = super::super::ParseState::new();
-    let tokens = vec![Token::Text("Hello world".to_string())];
+    let tokens = vec![Token::Text("Hello world".into())];
     let result = parse_inline(tokens, &mut state);
     let expected = make_paragraph(vec![text("Hello world")]);
     assert_eq!(result, expected);
   }
 
+  #[test]
+  fn test_block_markers_as_text() {
+    let mut state = super::super::ParseState::new();
+    let nz = |v: usize| std::num::NonZeroUsize::new(v).unwrap();
+    let tokens = vec![
+      Token::Text("a".into()),
+      Token::CellSeparator(Some(ast::table_cell::Style::RightAligned)),
+      Token::Heading(nz(2)),
+      Token::BlockQuote(nz(1)),
+      Token::HorizontalRule,
+    ];
+    let result = parse_inline(tokens, &mut state);
+    let expected = make_paragraph(vec![text("a"), text("||>"), text("++ "), text("> "), text("----")]);
+    assert_eq!(result, expected);
+  }
+
   #[test]
   fn test_multiple_text_tokens() {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
-      Token::Text("Hello".to_string()),
-      Token::Text(" ".to_string()),
-      Token::Text("world".to_string()),
+      Token::Text("Hello".into()),
+      Token::Text(" ".into()),
+      Token::Text("world".into()),
     ];
     let result = parse_inline(tokens, &mut state);
     let expected = make_paragraph(vec![
@@ -240,7 +467,7 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::Bold,
-      Token::Text("bold text".to_string()),
+      Token::Text("bold text".into()),
       Token::Bold,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -255,7 +482,7 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::Italics,
-      Token::Text("italic text".to_string()),
+      Token::Text("italic text".into()),
       Token::Italics,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -270,7 +497,7 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::Underline,
-      Token::Text("underlined".to_string()),
+      Token::Text("underlined".into()),
       Token::Underline,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -285,7 +512,7 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::Strikethrough,
-      Token::Text("struck".to_string()),
+      Token::Text("struck".into()),
       Token::Strikethrough,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -300,7 +527,7 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::Superscript,
-      Token::Text("super".to_string()),
+      Token::Text("super".into()),
       Token::Superscript,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -315,7 +542,7 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::Subscript,
-      Token::Text("sub".to_string()),
+      Token::Text("sub".into()),
       Token::Subscript,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -330,7 +557,7 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::MonospacedOpen,
-      Token::Text("code".to_string()),
+      Token::Text("code".into()),
       Token::MonospacedClose,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -345,11 +572,11 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::Bold,
-      Token::Text("bold ".to_string()),
+      Token::Text("bold ".into()),
       Token::Italics,
-      Token::Text("and italic".to_string()),
+      Token::Text("and italic".into()),
       Token::Italics,
-      Token::Text(" text".to_string()),
+      Token::Text(" text".into()),
       Token::Bold,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -368,10 +595,10 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::Bold,
-      Token::Text("bold".to_string()),
+      Token::Text("bold".into()),
       Token::Bold,
       Token::Italics,
-      Token::Text("italic".to_string()),
+      Token::Text("italic".into()),
       Token::Italics,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -386,8 +613,8 @@ mod tests {
   fn test_color_by_name() {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
-      Token::ColoredBeginColorName("red".to_string()),
-      Token::Text("red text".to_string()),
+      Token::ColoredBeginColorName("red".into()),
+      Token::Text("red text".into()),
       Token::ColoredEnd,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -406,8 +633,8 @@ mod tests {
   fn test_color_by_code() {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
-      Token::ColoredBeginColorCode("FF0000".to_string()),
-      Token::Text("red text".to_string()),
+      Token::ColoredBeginColorCode("FF0000".into()),
+      Token::Text("red text".into()),
       Token::ColoredEnd,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -426,8 +653,8 @@ mod tests {
   fn test_color_lowercase_code() {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
-      Token::ColoredBeginColorCode("0000ff".to_string()),
-      Token::Text("blue".to_string()),
+      Token::ColoredBeginColorCode("0000ff".into()),
+      Token::Text("blue".into()),
       Token::ColoredEnd,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -466,8 +693,8 @@ mod tests {
 
     for (name, (r, g, b)) in colors {
       let tokens = vec![
-        Token::ColoredBeginColorName(name.to_string()),
-        Token::Text("text".to_string()),
+        Token::ColoredBeginColorName(name.into()),
+        Token::Text("text".into()),
         Token::ColoredEnd,
       ];
       let result = parse_inline(tokens, &mut state);
@@ -488,16 +715,19 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::NamedLink {
-        link: "https://example.com".to_string(),
-        name: "click here".to_string(),
+        link: "https://example.com".into(),
+        name: "click here".into(),
+        new_tab: false,
+        source: "[https://example.com click here]".into(),
       },
     ];
     let result = parse_inline(tokens, &mut state);
     let expected = make_paragraph(vec![
       TreeElement::Link {
-        href: crate::ast::Url("https://example.com".to_string()),
+        href: url("https://example.com"),
         open_in_new_tab: false,
-        name: "click here".to_string(),
+        missing: false,
+        children: vec![text("click here")],
       },
     ]);
     assert_eq!(result, expected);
@@ -508,28 +738,153 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::PageLink {
-        link: "about/author".to_string(),
-        name: "author page".to_string(),
+        link: "about/author".into(),
+        target: page("about/author"),
+        name: "author page".into(),
+        new_tab: false,
       },
     ];
     let result = parse_inline(tokens, &mut state);
     let expected = make_paragraph(vec![
       TreeElement::Link {
-        href: ast::Url("/about/author".to_string()),
+        href: url("/about-author"),
         open_in_new_tab: false,
-        name: "author page".to_string(),
+        missing: false,
+        children: vec![text("author page")],
       },
     ]);
     assert_eq!(result, expected);
   }
 
+  #[test]
+  fn test_link_label_wikitext() {
+    let mut state = super::super::ParseState::new();
+    let tokens = crate::tokenizer::tokenize("[https://example.com a **b** [https://example.org c]] [[[page|+ ##red|d## https://example.net]]]");
+    let result = parse_inline(tokens, &mut state);
+    let expected = make_paragraph(vec![
+      TreeElement::Link { href: url("https://example.com"), open_in_new_tab: false, missing: false, children: vec![
+        text("a "),
+        TreeElement::Bold(vec![text("b")]),
+        text(" ["),
+        text("https://example.org"),
+        text(" c"),
+      ] },
+      text("] "),
+      TreeElement::Link { href: url("/page"), open_in_new_tab: false, missing: false, children: vec![
+        text("+ "),
+        TreeElement::Colored { red: 0xFF, green: 0, blue: 0, children: vec![text("d")] },
+        text(" "),
+        text("https://example.net"),
+      ] },
+    ]);
+    assert_eq!(result, expected);
+  }
+
+  #[test]
+  fn test_nested_html_link() {
+    let mut state = super::super::ParseState::new();
+    let tokens = crate::tokenizer::tokenize(r#"[[a href="/x"]]a [[a href="/y"]]b[[/a]] [[[page|c]]][[/a]]"#);
+    let result = parse_inline(tokens, &mut state);
+    let expected = make_paragraph(vec![
+      TreeElement::HtmlElement { tag: String::from("a"), property: vec![(String::from("href"), String::from("/x"))], children: vec![
+        text("a "),
+        text("b"),
+        text(" "),
+        text("c"),
+      ] },
+    ]);
+    assert_eq!(result, expected);
+  }
+
+  #[test]
+  fn test_explicit_breaks() {
+    let mut state = super::super::ParseState::new();
+    let tokens = crate::tokenizer::tokenize("a[[br]]b _\nc[[lines 2]]d[[lines 100000]]");
+    let result = parse_inline(tokens, &mut state);
+    let mut expected = vec![
+      text("a"), TreeElement::NewLine,
+      text("b"), TreeElement::NewLine,
+      text("c"), TreeElement::NewLine, TreeElement::NewLine,
+      text("d"),
+    ];
+    expected.extend(std::iter::repeat_n(TreeElement::NewLine, constants::MAX_LINES));
+    assert_eq!(result, make_paragraph(expected));
+  }
+
+  #[test]
+  fn test_anchor() {
+    let options = crate::options::Options { id_prefix: String::from("p-"), ..Default::default() };
+    let mut state = super::super::ParseState::with_options(options);
+    let tokens = vec![
+      Token::ElementBegin { name: "#".into(), attributes: vec![("".into(), " top ".into())], arguments: vec![] },
+      Token::NamedLink { link: "#top".into(), name: "Back".into(), new_tab: false, source: "[#top Back]".into() },
+      Token::PageLink { link: "#top".into(), target: page("#top"), name: "Top".into(), new_tab: false },
+    ];
+    let result = parse_inline(tokens, &mut state);
+    let expected = make_paragraph(vec![
+      TreeElement::Anchor(String::from("top")),
+      TreeElement::Link { href: ast::Url::fragment("p-top"), open_in_new_tab: false, missing: false, children: vec![text("Back")] },
+      TreeElement::Link { href: ast::Url::fragment("p-top"), open_in_new_tab: false, missing: false, children: vec![text("Top")] },
+    ]);
+    assert_eq!(result, expected);
+  }
+
+  #[test]
+  fn test_page_link_label_fallback() {
+    struct Titles;
+    impl crate::link::LinkResolver for Titles {
+      fn title(&self, page: &crate::link::PageLink) -> Option<String> {
+        (page.page == "scp-173").then(|| String::from("The Sculpture"))
+      }
+    }
+
+    let options = crate::options::Options { link_resolver: Some(std::sync::Arc::new(Titles)), ..Default::default() };
+    let mut state = super::super::ParseState::with_options(options);
+    let tokens = vec![
+      Token::PageLink { link: "SCP-173".into(), target: page("SCP-173"), name: "".into(), new_tab: false },
+      Token::PageLink { link: " Other Page ".into(), target: page("Other Page"), name: "".into(), new_tab: false },
+    ];
+    let result = parse_inline(tokens, &mut state);
+    let expected = make_paragraph(vec![
+      TreeElement::Link { href: url("/scp-173"), open_in_new_tab: false, missing: false, children: vec![text("The Sculpture")] },
+      TreeElement::Link { href: url("/other-page"), open_in_new_tab: false, missing: false, children: vec![text("Other Page")] },
+    ]);
+    assert_eq!(result, expected);
+  }
+
+  #[test]
+  fn test_page_link_resolver() {
+    struct Pages;
+    impl crate::link::LinkResolver for Pages {
+      fn url(&self, page: &crate::link::PageLink) -> Option<String> {
+        Some(format!("/wiki/{}", page.full_name()))
+      }
+      fn exists(&self, page: &crate::link::PageLink) -> bool {
+        page.page == "main"
+      }
+    }
+
+    let options = crate::options::Options { link_resolver: Some(std::sync::Arc::new(Pages)), ..Default::default() };
+    let mut state = super::super::ParseState::with_options(options);
+    let tokens = vec![
+      Token::PageLink { link: "main".into(), target: page("main"), name: "a".into(), new_tab: false },
+      Token::PageLink { link: "Draft".into(), target: page("Draft"), name: "b".into(), new_tab: false },
+    ];
+    let result = parse_inline(tokens, &mut state);
+    let expected = make_paragraph(vec![
+      TreeElement::Link { href: url("/wiki/main"), open_in_new_tab: false, missing: false, children: vec![text("a")] },
+      TreeElement::Link { href: url("/wiki/draft"), open_in_new_tab: false, missing: true, children: vec![text("b")] },
+    ]);
+    assert_eq!(result, expected);
+  }
+
   #[test]
   fn test_newline() {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
-      Token::Text("line1".to_string()),
+      Token::Text("line1".into()),
       Token::NewLine,
-      Token::Text("line2".to_string()),
+      Token::Text("line2".into()),
     ];
     let result = parse_inline(tokens, &mut state);
     let expected = make_paragraph(vec![
@@ -544,10 +899,10 @@ mod tests {
   fn test_multiple_newlines() {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
-      Token::Text("a".to_string()),
+      Token::Text("a".into()),
       Token::NewLine,
       Token::NewLine,
-      Token::Text("b".to_string()),
+      Token::Text("b".into()),
     ];
     let result = parse_inline(tokens, &mut state);
     let expected = make_paragraph(vec![
@@ -564,8 +919,8 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::Bold,
-      Token::ColoredBeginColorName("blue".to_string()),
-      Token::Text("blue bold".to_string()),
+      Token::ColoredBeginColorName("blue".into()),
+      Token::Text("blue bold".into()),
       Token::ColoredEnd,
       Token::Bold,
     ];
@@ -588,15 +943,15 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::Bold,
-      Token::Text("b".to_string()),
+      Token::Text("b".into()),
       Token::Italics,
-      Token::Text("bi".to_string()),
+      Token::Text("bi".into()),
       Token::MonospacedOpen,
-      Token::Text("bim".to_string()),
+      Token::Text("bim".into()),
       Token::MonospacedClose,
-      Token::Text("bi2".to_string()),
+      Token::Text("bi2".into()),
       Token::Italics,
-      Token::Text("b2".to_string()),
+      Token::Text("b2".into()),
       Token::Bold,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -633,9 +988,9 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::Bold,
-      Token::Text("E".to_string()),
+      Token::Text("E".into()),
       Token::Superscript,
-      Token::Text("2".to_string()),
+      Token::Text("2".into()),
       Token::Superscript,
       Token::Bold,
     ];
@@ -654,9 +1009,9 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::Bold,
-      Token::Text("H".to_string()),
+      Token::Text("H".into()),
       Token::Subscript,
-      Token::Text("2".to_string()),
+      Token::Text("2".into()),
       Token::Subscript,
       Token::Bold,
     ];
@@ -674,11 +1029,11 @@ mod tests {
   fn test_color_with_bold_and_italics() {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
-      Token::ColoredBeginColorName("green".to_string()),
+      Token::ColoredBeginColorName("green".into()),
       Token::Bold,
-      Token::Text("green".to_string()),
+      Token::Text("green".into()),
       Token::Italics,
-      Token::Text("text".to_string()),
+      Token::Text("text".into()),
       Token::Italics,
       Token::Bold,
       Token::ColoredEnd,
@@ -704,15 +1059,15 @@ mod tests {
   fn test_monospaced_complex() {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
-      Token::Text("prefix ".to_string()),
+      Token::Text("prefix ".into()),
       Token::MonospacedOpen,
-      Token::Text("mono ".to_string()),
+      Token::Text("mono ".into()),
       Token::Bold,
-      Token::Text("bold in mono".to_string()),
+      Token::Text("bold in mono".into()),
       Token::Bold,
-      Token::Text(" more mono".to_string()),
+      Token::Text(" more mono".into()),
       Token::MonospacedClose,
-      Token::Text(" suffix".to_string()),
+      Token::Text(" suffix".into()),
     ];
     let result = parse_inline(tokens, &mut state);
     let expected = make_paragraph(vec![
@@ -732,27 +1087,33 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::NamedLink {
-        link: "https://example.com".to_string(),
-        name: "link1".to_string(),
+        link: "https://example.com".into(),
+        name: "link1".into(),
+        new_tab: false,
+        source: "[https://example.com link1]".into(),
       },
-      Token::Text(" ".to_string()),
+      Token::Text(" ".into()),
       Token::PageLink {
-        link: "page2".to_string(),
-        name: "link2".to_string(),
+        link: "page2".into(),
+        target: page("page2"),
+        name: "link2".into(),
+        new_tab: false,
       },
     ];
     let result = parse_inline(tokens, &mut state);
     let expected = make_paragraph(vec![
       TreeElement::Link {
-        href: crate::ast::Url("https://example.com".to_string()),
+        href: url("https://example.com"),
         open_in_new_tab: false,
-        name: "link1".to_string(),
+        missing: false,
+        children: vec![text("link1")],
       },
       text(" "),
       TreeElement::Link {
-        href: crate::ast::Url("/page2".to_string()),
+        href: url("/page2"),
         open_in_new_tab: false,
-        name: "link2".to_string(),
+        missing: false,
+        children: vec![text("link2")],
       },
     ]);
     assert_eq!(result, expected);
@@ -764,12 +1125,13 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::Bold,
-      Token::Text("unclosed bold".to_string()),
+      Token::Text("unclosed bold".into()),
     ];
     let result = parse_inline(tokens, &mut state);
-    // Unclosed elements remain open in the paragraph
+    // Unclosed markers are printed as they are
     let expected = make_paragraph(vec![
-      TreeElement::Bold(vec![text("unclosed bold")]),
+      text("**"),
+      text("unclosed bold"),
     ]);
     assert_eq!(result, expected);
   }
@@ -778,29 +1140,115 @@ mod tests {
   fn test_unopened_closing_token() {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
-      Token::Text("text".to_string()),
+      Token::Text("text".into()),
       Token::Bold,
     ];
     let result = parse_inline(tokens, &mut state);
-    // Unopened closing token creates an empty element
     let expected = make_paragraph(vec![
       text("text"),
-      TreeElement::Bold(vec![]),
+      text("**"),
+    ]);
+    assert_eq!(result, expected);
+  }
+
+  #[test]
+  fn test_flanking_markers() {
+    let mut state = super::super::ParseState::new();
+    // 2 ** 3 and **b **
+    let tokens = vec![
+      Token::Text("2 ".into()),
+      Token::Bold,
+      Token::Text(" 3 and ".into()),
+      Token::Bold,
+      Token::Text("b ".into()),
+      Token::Bold,
+    ];
+    let result = parse_inline(tokens, &mut state);
+    let expected = make_paragraph(vec![
+      text("2 "),
+      text("**"),
+      text(" 3 and "),
+      text("**"),
+      text("b "),
+      text("**"),
+    ]);
+    assert_eq!(result, expected);
+  }
+
+  #[test]
+  fn test_unmatched_markers_are_text() {
+    let mut state = super::super::ParseState::new();
+    // **a //b** c
+    let tokens = vec![
+      Token::Bold,
+      Token::Text("a ".into()),
+      Token::Italics,
+      Token::Text("b".into()),
+      Token::Bold,
+      Token::Text(" c".into()),
+      Token::MonospacedClose,
+      Token::ColoredBeginColorName("red".into()),
+    ];
+    let result = parse_inline(tokens, &mut state);
+    let expected = make_paragraph(vec![
+      TreeElement::Bold(vec![text("a "), text("//"), text("b")]),
+      text(" c"),
+      text("}}"),
+      text("##red|"),
     ]);
     assert_eq!(result, expected);
   }
 
+  #[test]
+  fn test_markers_close_by_role() {
+    let mut state = super::super::ParseState::new();
+    // **a **b** c**: the second ** follows whitespace, so it opens instead of closing
+    let tokens = vec![
+      Token::Bold,
+      Token::Text("a ".into()),
+      Token::Bold,
+      Token::Text("b".into()),
+      Token::Bold,
+      Token::Text(" c".into()),
+      Token::Bold,
+    ];
+    let result = parse_inline(tokens, &mut state);
+    let expected = make_paragraph(vec![
+      TreeElement::Bold(vec![text("a "), TreeElement::Bold(vec![text("b")]), text(" c")]),
+    ]);
+    assert_eq!(result, expected);
+
+    let result = parse_inline(crate::tokenizer::tokenize("//a //b// c//"), &mut state);
+    let expected = make_paragraph(vec![
+      TreeElement::Italics(vec![text("a "), TreeElement::Italics(vec![text("b")]), text(" c")]),
+    ]);
+    assert_eq!(result, expected);
+  }
+
+  #[test]
+  fn test_many_openers() {
+    let mut state = super::super::ParseState::new();
+    let mut tokens = vec![];
+    for _ in 0..20000 {
+      tokens.push(Token::Bold);
+      tokens.push(Token::Text("a ".into()));
+    }
+    // 閉じられない記号ばかりなので全部文字になる
+    let result = parse_inline(tokens, &mut state);
+    assert!(result.iter().all(|v| matches!(v, TreeElement::Text(_))));
+  }
+
   #[test]
   fn test_interleaved_formatting_bold_italics() {
     // This represents **a//b**c//d (wrongly-layered)
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::Bold,
-      Token::Text("a".to_string()),
+      Token::Text("a".into()),
       Token::Italics,
-      Token::Text("b".to_string()),
+      Token::Text("b".into()),
       Token::Bold,
-      Token::Text("c".to_string()),
+      Token::Text("c".into()),
       Token::Italics,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -819,11 +1267,11 @@ mod tests {
   fn test_interleaved_colors_and_bold() {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
-      Token::ColoredBeginColorName("red".to_string()),
+      Token::ColoredBeginColorName("red".into()),
       Token::Bold,
-      Token::Text("text".to_string()),
+      Token::Text("text".into()),
       Token::ColoredEnd,
-      Token::Text("more".to_string()),
+      Token::Text("more".into()),
       Token::Bold,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -846,11 +1294,11 @@ mod tests {
   fn test_multiple_colors_sequential() {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
-      Token::ColoredBeginColorName("red".to_string()),
-      Token::Text("red".to_string()),
+      Token::ColoredBeginColorName("red".into()),
+      Token::Text("red".into()),
       Token::ColoredEnd,
-      Token::ColoredBeginColorName("blue".to_string()),
-      Token::Text("blue".to_string()),
+      Token::ColoredBeginColorName("blue".into()),
+      Token::Text("blue".into()),
       Token::ColoredEnd,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -877,15 +1325,15 @@ mod tests {
     let mut state = super::super::ParseState::new();
     let tokens = vec![
       Token::Bold,
-      Token::Text("a".to_string()),
+      Token::Text("a".into()),
       Token::Bold,
-      Token::Text("b".to_string()),
+      Token::Text("b".into()),
       Token::Italics,
-      Token::Text("c".to_string()),
+      Token::Text("c".into()),
       Token::Italics,
-      Token::Text("d".to_string()),
+      Token::Text("d".into()),
       Token::Bold,
-      Token::Text("e".to_string()),
+      Token::Text("e".into()),
       Token::Bold,
     ];
     let result = parse_inline(tokens, &mut state);
@@ -898,4 +1346,160 @@ mod tests {
     ]);
     assert_eq!(result, expected);
   }
-}
\ No newline at end of file
+  #[test]
+  fn test_collapsible_defaults() {
+    let mut state = super::super::ParseState::new();
+    let tokens = vec![
+      Token::ElementBegin { name: "collapsible".into(), attributes: vec![], arguments: vec![] },
+      Token::Text("body".into()),
+      Token::ElementEnd("collapsible".into()),
+    ];
+    let result = parse_inline(tokens, &mut state);
+    let expected = make_paragraph(vec![
+      TreeElement::Collapsible {
+        text_open: "+ show block".to_string(),
+        text_closed: "- hide block".to_string(),
+        folded: true,
+        hide_location: ast::HideLocation::Top,
+        children: vec![text("body")],
+      },
+    ]);
+    assert_eq!(result, expected);
+  }
+
+  #[test]
+  fn test_collapsible_attributes() {
+    let mut state = super::super::ParseState::new();
+    let tokens = vec![
+      Token::ElementBegin { name: "collapsible".into(), attributes: vec![
+        ("show".into(), "open me".into()),
+        ("hide".into(), "close me".into()),
+        ("folded".into(), "no".into()),
+        ("hideLocation".into(), "both".into()),
+      ], arguments: vec![] },
+      Token::Text("body".into()),
+      Token::ElementEnd("collapsible".into()),
+    ];
+    let result = parse_inline(tokens, &mut state);
+    let expected = make_paragraph(vec![
+      TreeElement::Collapsible {
+        text_open: "open me".to_string(),
+        text_closed: "close me".to_string(),
+        folded: false,
+        hide_location: ast::HideLocation::Both,
+        children: vec![text("body")],
+      },
+    ]);
+    assert_eq!(result, expected);
+  }
+
+  #[test]
+  fn test_span_attributes_sanitized() {
+    let mut state = super::super::ParseState::new();
+    let tokens = vec![
+      Token::ElementBegin { name: "span".into(), attributes: vec![
+        ("style".into(), "color: red; position: fixed; top: 0".into()),
+        ("onmouseover".into(), "alert(1)".into()),
+        ("class".into(), "note".into()),
+      ], arguments: vec![] },
+      Token::Text("text".into()),
+      Token::ElementEnd("span".into()),
+    ];
+    let result = parse_inline(tokens, &mut state);
+    let expected = make_paragraph(vec![
+      TreeElement::HtmlElement {
+        tag: "span".to_string(),
+        property: vec![
+          ("style".to_string(), "color: red; top: 0".to_string()),
+          ("class".to_string(), "note".to_string()),
+        ],
+        children: vec![text("text")],
+      },
+    ]);
+    assert_eq!(result, expected);
+  }
+
+  #[test]
+  fn test_size_rejects_injection() {
+    let mut state = super::super::ParseState::new();
+    let tokens = vec![
+      Token::ElementBegin { name: "size".into(), attributes: vec![
+        ("".into(), "1em;position:fixed".into()),
+      ], arguments: vec![] },
+      Token::Text("text".into()),
+      Token::ElementEnd("size".into()),
+    ];
+    let result = parse_inline(tokens, &mut state);
+    let expected = make_paragraph(vec![
+      TreeElement::Size { scale: CssSize::new("1em"), children: vec![text("text")] },
+    ]);
+    assert_eq!(result, expected);
+  }
+
+  #[test]
+  fn test_named_link_disallowed_scheme() {
+    let mut state = super::super::ParseState::new();
+    let tokens = vec![
+      Token::NamedLink {
+        link: "javascript:alert(1)".into(),
+        name: "click here".into(),
+        new_tab: false,
+        source: "[javascript:alert(1) click here]".into(),
+      },
+    ];
+    let result = parse_inline(tokens, &mut state);
+    let expected = make_paragraph(vec![text("[javascript:alert(1) click here]")]);
+    assert_eq!(result, expected);
+  }
+
+  #[test]
+  fn test_html_link_href_validated() {
+    let mut state = super::super::ParseState::new();
+    let tokens = vec![
+      Token::ElementBegin { name: "a".into(), attributes: vec![
+        ("href".into(), "javascript:alert(1)".into()),
+      ], arguments: vec![] },
+      Token::Text("a".into()),
+      Token::ElementEnd("a".into()),
+      Token::ElementBegin { name: "a".into(), attributes: vec![
+        ("href".into(), "https://example.com/a b".into()),
+      ], arguments: vec![] },
+      Token::Text("b".into()),
+      Token::ElementEnd("a".into()),
+    ];
+    let result = parse_inline(tokens, &mut state);
+    let expected = make_paragraph(vec![
+      TreeElement::HtmlElement { tag: "a".to_string(), property: vec![], children: vec![text("a")] },
+      TreeElement::HtmlElement {
+        tag: "a".to_string(),
+        property: vec![("href".to_string(), "https://example.com/a%20b".to_string())],
+        children: vec![text("b")],
+      },
+    ]);
+    assert_eq!(result, expected);
+  }
+
+  #[test]
+  fn test_image_and_iframe() {
+    let mut state = super::super::ParseState::new();
+    state.options.url.base = Some("https://example.com/".to_string());
+    let tokens = vec![
+      Token::ElementBegin { name: "image".into(), attributes: vec![
+        ("".into(), "photo.png".into()),
+        ("alt".into(), "a photo".into()),
+      ], arguments: vec![] },
+      Token::ElementBegin { name: "iframe".into(), attributes: vec![
+        ("".into(), "javascript:alert(1)".into()),
+      ], arguments: vec![] },
+      Token::ElementBegin { name: "iframe".into(), attributes: vec![
+        ("".into(), "https://example.org/embed".into()),
+      ], arguments: vec![] },
+    ];
+    let result = parse_inline(tokens, &mut state);
+    let expected = make_paragraph(vec![
+      TreeElement::Image { src: url("https://example.com/photo.png"), alt: "a photo".to_string() },
+      TreeElement::ExternalIframe { src: url("https://example.org/embed") },
+    ]);
+    assert_eq!(result, expected);
+  }
+}
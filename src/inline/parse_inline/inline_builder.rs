@@ -3,8 +3,6 @@ use crate::ast::{self, TreeElement};
 pub struct InlineBuilder {
   root: Vec<ast::TreeElement>,
   data: Vec<(ast::ParseFrame, Vec<ast::TreeElement>)>,
-  footnotes: Vec<Vec<ast::TreeElement>>,
-  footnoteoffset: usize,
 }
 
 impl InlineBuilder {
@@ -12,8 +10,6 @@ impl InlineBuilder {
     Self {
       root: vec![],
       data: vec![],
-      footnotes: vec![],
-      footnoteoffset: 0,
     }
   }
 
@@ -51,17 +47,6 @@ impl InlineBuilder {
     reached
   }
 
-  pub fn switch_element(&mut self, param_frame: ast::ParseFrame) {
-    for (frame, _) in &self.data {
-      if frame.get_kind() == param_frame.get_kind() {
-        self.close_element(param_frame.get_kind()); // if found: open
-        return;
-      }
-    }
-
-    self.push(param_frame); // if not found: close
-  }
-
   pub fn push(&mut self, frame: ast::ParseFrame) {
     self.data.push((frame, vec![]));
   }
@@ -74,6 +59,10 @@ impl InlineBuilder {
     }
   }
 
+  pub fn is_inside(&self, kind: &ast::ParseFrameKind) -> bool {
+    self.data.iter().any(|(frame, _)| frame.get_kind() == *kind)
+  }
+
   pub fn get_now_children(&mut self) -> Vec<TreeElement> {
     if let Some(v) = self.data.pop() {
       v.1
@@ -85,7 +74,7 @@ impl InlineBuilder {
 
 impl From<InlineBuilder> for Vec<ast::TreeElement> {
   fn from(mut builder: InlineBuilder) -> Vec<ast::TreeElement> {
-    while let Some(_) = builder.pop_and_merge() {}
+    while builder.pop_and_merge().is_some() {}
     builder.root
   }
 }
\ No newline at end of file
@@ -7,6 +7,11 @@ pub enum TagKind {
   FootnoteTarget, // Wikidot Custom: [[footnoteblock]]
   Include, // Wikidot Custom: [[include ...]]
   Div, // HTML `div` element
+  Image, // Wikidot Custom: [[image source]]
+  Iframe, // Wikidot Custom: [[iframe url]]
+  Anchor, // Wikidot Custom: [[# name]]
+  LineBreak, // [[br]]
+  Lines, // [[lines n]]: n個の改行
 }
 
 pub fn get_tag_kind_from_str(s: &str) -> Option<TagKind> {
@@ -19,6 +24,11 @@ pub fn get_tag_kind_from_str(s: &str) -> Option<TagKind> {
     "footnoteblock" => Some(TagKind::FootnoteTarget),
     "include" => Some(TagKind::Include),
     "div" => Some(TagKind::Div),
+    "image" => Some(TagKind::Image),
+    "iframe" => Some(TagKind::Iframe),
+    "#" => Some(TagKind::Anchor),
+    "br" => Some(TagKind::LineBreak),
+    "lines" => Some(TagKind::Lines),
     &_ => None
   }
 }
\ No newline at end of file
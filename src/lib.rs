@@ -1,22 +1,69 @@
-mod tokenizer;
+pub mod tokenizer;
 mod block;
 mod inline;
 mod ast;
 mod renderer;
 mod constants;
+pub mod options;
+pub mod sanitize;
+pub mod locale;
+pub mod url;
+pub mod link;
+pub mod module;
+pub mod repository;
+pub mod diagnostic;
+pub mod event;
+
+pub use options::Options;
 
 pub fn parse(s: String) -> String {
-  // get LF string
-  let s = s.replace("\r\n", "\n"); // CRLF -> LF
-  let s = s.replace("\r", "\n"); // CR -> LF
+  parse_with_options(s, &Options::default())
+}
+
+pub fn parse_with_options(s: String, options: &Options) -> String {
+  parse_with_diagnostics(s, options).0
+}
+
+/// Same as `parse_with_options`, also returning the problems found in the source.
+pub fn parse_with_diagnostics(s: String, options: &Options) -> (String, Vec<diagnostic::Diagnostic>) {
+  let s = to_lf(s);
+
+  // tokens and blocks are produced on demand, while the inline parser reads them
+  let mut tokens = tokenizer::Tokenizer::new(&s);
+  let (ast, inline_diagnostics) = inline::parse_with_diagnostics(block::BlockParser::new(&mut tokens), options);
+  let mut diagnostics = tokens.into_diagnostics();
+  diagnostics.extend(inline_diagnostics);
+  (renderer::xhtml::render(ast, options), diagnostics)
+}
 
-  let token = tokenizer::tokenize(s);
-  println!("{:#?}", token);
-  let block_tree = block::parse(token);
-  println!("{:#?}", block_tree);
-  let ast = inline::parse(block_tree);
-  println!("{:#?}", ast);
-  let html = renderer::xhtml::render(ast);
+/// Renders the page without `<html>`, `<head>` and `<body>`, e.g. to embed it in another page.
+/// Stylesheets of `[[module CSS]]` are left out.
+pub fn parse_fragment(s: String, options: &Options) -> String {
+  let s = to_lf(s);
 
-  html
+  let ast = inline::parse(block::BlockParser::new(tokenizer::Tokenizer::new(&s)), options);
+  renderer::xhtml::render_fragment(ast, options)
+}
+
+/// Renders only the first `blocks` top-level blocks (paragraphs, headings, tables, ...), e.g. for previews.
+/// The rest of the page is not parsed.
+pub fn parse_preview(s: String, options: &Options, blocks: usize) -> String {
+  let s = to_lf(s);
+
+  let ast = inline::parse(block::BlockParser::new(tokenizer::Tokenizer::new(&s)).take(blocks), options);
+  renderer::xhtml::render(ast, options)
+}
+
+/// Reads the page as a stream of `event::Event`s instead of rendering it. `s` must use LF line endings.
+pub fn parse_events<'a>(s: &'a str, options: &'a Options) -> event::Events<'a> {
+  event::Events::new(s, options)
+}
+
+// get LF string
+fn to_lf(s: String) -> String {
+  if !s.contains('\r') {
+    return s;
+  }
+  let s = s.replace("\r\n", "\n"); // CRLF -> LF
+  s.replace("\r", "\n") // CR -> LF
 }
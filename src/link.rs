@@ -0,0 +1,210 @@
+/// How links written in a page are rendered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkPolicy {
+  /// Adds `rel="nofollow ugc"` to links to other sites, so that search engines do not count them for the site.
+  pub nofollow_external: bool,
+  /// Domain of the sites linked by `[[[:site:page]]]`.
+  pub site_domain: String,
+}
+
+impl Default for LinkPolicy {
+  fn default() -> Self {
+    Self {
+      nofollow_external: false,
+      site_domain: String::from("wikidot.com"),
+    }
+  }
+}
+
+impl LinkPolicy {
+  /// Value of the `rel` attribute of a link, if it needs one.
+  pub fn rel(&self, href: &crate::url::Url, url_policy: &crate::url::UrlPolicy, open_in_new_tab: bool) -> Option<String> {
+    let mut values = vec![];
+    if self.nofollow_external && is_external(href, url_policy) {
+      values.extend(["nofollow", "ugc"]);
+    }
+    if open_in_new_tab {
+      values.extend(["noopener", "noreferrer"]);
+    }
+    (!values.is_empty()).then(|| values.join(" "))
+  }
+}
+
+/// Looks up the pages linked from a page, e.g. from a database of the site. Set it in `Options::link_resolver`.
+pub trait LinkResolver: Send + Sync {
+  /// URL of the page. `PageLink::href` is used if this returns `None`.
+  fn url(&self, _page: &PageLink) -> Option<String> {
+    None
+  }
+
+  /// Whether the page exists. Links to missing pages get the `newpage` class.
+  fn exists(&self, _page: &PageLink) -> bool {
+    true
+  }
+
+  /// Title of the page, used as the label of `[[[page]]]` links without one.
+  fn title(&self, _page: &PageLink) -> Option<String> {
+    None
+  }
+}
+
+impl std::fmt::Debug for dyn LinkResolver {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str("LinkResolver")
+  }
+}
+
+/// Target of `[[[page]]]`, written as `[:site:][category:]page[#anchor]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageLink {
+  /// Other site on Wikidot, from `[[[:site:page]]]`.
+  pub site: Option<String>,
+  /// `None` for pages in the `_default` category.
+  pub category: Option<String>,
+  /// Unix name of the page, e.g. `scp-173`. Empty if only the anchor is given.
+  pub page: String,
+  pub anchor: Option<String>,
+}
+
+impl PageLink {
+  /// Returns `None` if neither a page nor an anchor is given.
+  pub fn parse(s: &str) -> Option<Self> {
+    let s = s.trim();
+    let (s, anchor) = match s.split_once('#') {
+      Some((s, anchor)) => (s, Some(anchor.trim()).filter(|v| !v.is_empty()).map(String::from)),
+      None => (s, None),
+    };
+
+    let (site, s) = match s.strip_prefix(':').and_then(|v| v.split_once(':')) {
+      Some((site, s)) => (Some(unix_name(site)).filter(|v| !v.is_empty()), s),
+      None => (None, s),
+    };
+
+    let name = unix_name(s);
+    let (category, page) = match name.split_once(':') {
+      Some((category, page)) => (Some(category).filter(|v| *v != "_default").map(String::from), String::from(page)),
+      None => (None, name),
+    };
+
+    if page.is_empty() && anchor.is_none() {
+      return None;
+    }
+    Some(Self { site, category, page, anchor })
+  }
+
+  /// `category:page`, or `page` in the `_default` category.
+  pub fn full_name(&self) -> String {
+    match &self.category {
+      Some(category) => format!("{}:{}", category, self.page),
+      None => self.page.clone(),
+    }
+  }
+
+  /// URL of the page, relative to the site unless it is on another site.
+  /// Anchors in the same page get `Options::id_prefix`, like the ids they point at.
+  pub fn href(&self, options: &crate::options::Options) -> String {
+    let mut res = match &self.site {
+      Some(site) => format!("https://{}.{}/{}", site, options.link.site_domain, self.full_name()),
+      None if self.page.is_empty() => String::new(),
+      None => format!("/{}", self.full_name()),
+    };
+    if let Some(anchor) = &self.anchor {
+      let anchor = if self.site.is_none() && self.page.is_empty() { options.element_id(anchor) } else { anchor.clone() };
+      res += crate::url::Url::fragment(&anchor).as_str();
+    }
+    res
+  }
+}
+
+/// Wikidot's page name normalization: lowercase, and characters other than letters, digits, `:` and `_` become hyphens.
+/// `_` is only kept at the start of a name, e.g. `_template`.
+pub fn unix_name(s: &str) -> String {
+  let mut res = String::new();
+  for c in s.trim().chars() {
+    let c = c.to_ascii_lowercase();
+    let c = match c {
+      'a'..='z' | '0'..='9' | ':' => c,
+      '_' if res.is_empty() || res.ends_with(':') => c,
+      _ => '-',
+    };
+
+    match c {
+      // 連続する記号をまとめ, `:` の前後のハイフンを取り除く
+      '-' if res.is_empty() || res.ends_with(['-', ':', '_']) => {}
+      ':' if res.ends_with(':') => {}
+      ':' => {
+        while res.ends_with('-') {
+          res.pop();
+        }
+        res.push(c);
+      }
+      _ => res.push(c),
+    }
+  }
+
+  res.trim_end_matches(['-', ':']).trim_start_matches(':').to_string()
+}
+
+/// Absolute URLs are external unless they have the same scheme, host and port as `UrlPolicy::base`.
+fn is_external(href: &crate::url::Url, url_policy: &crate::url::UrlPolicy) -> bool {
+  let base = url_policy.base.as_ref().and_then(|base| crate::url::Url::parse(base, url_policy).ok()).and_then(|base| base.origin());
+  href.is_absolute() && (base.is_none() || href.origin() != base)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::url::{Url, UrlPolicy};
+
+  #[test]
+  fn test_rel() {
+    let policy = LinkPolicy { nofollow_external: true, ..LinkPolicy::default() };
+    let url_policy = UrlPolicy { base: Some(String::from("https://example.wikidot.com/")), ..UrlPolicy::default() };
+
+    let external = Url::parse("https://example.com/", &url_policy).unwrap();
+    let internal = Url::parse("/page", &url_policy).unwrap();
+    assert_eq!(policy.rel(&external, &url_policy, false).as_deref(), Some("nofollow ugc"));
+    assert_eq!(policy.rel(&external, &url_policy, true).as_deref(), Some("nofollow ugc noopener noreferrer"));
+    assert_eq!(policy.rel(&internal, &url_policy, false), None);
+    assert_eq!(LinkPolicy::default().rel(&external, &url_policy, false), None);
+
+    // 文字列が base で始まっていても別のホスト
+    let url_policy = UrlPolicy { base: Some(String::from("https://example.com")), ..UrlPolicy::default() };
+    let lookalike = Url::parse("https://example.com.evil.org/", &url_policy).unwrap();
+    let same_site = Url::parse("https://EXAMPLE.com:443/page", &url_policy).unwrap();
+    assert_eq!(policy.rel(&lookalike, &url_policy, false).as_deref(), Some("nofollow ugc"));
+    assert_eq!(policy.rel(&same_site, &url_policy, false), None);
+  }
+
+  #[test]
+  fn test_unix_name() {
+    assert_eq!(unix_name("SCP-173"), "scp-173");
+    assert_eq!(unix_name(" Hello, World! "), "hello-world");
+    assert_eq!(unix_name("Category : Some_Page"), "category:some-page");
+    assert_eq!(unix_name("_template"), "_template");
+    assert_eq!(unix_name("ja:日本語"), "ja");
+  }
+
+  #[test]
+  fn test_page_link() {
+    let options = crate::options::Options::default();
+    let link = |s: &str| PageLink::parse(s).map(|v| v.href(&options));
+
+    assert_eq!(link("SCP-173"), Some(String::from("/scp-173")));
+    assert_eq!(link("page#anchor"), Some(String::from("/page#anchor")));
+    assert_eq!(link("Category:Page"), Some(String::from("/category:page")));
+    assert_eq!(link("_default:page"), Some(String::from("/page")));
+    assert_eq!(link(":othersite:page"), Some(String::from("https://othersite.wikidot.com/page")));
+    assert_eq!(link("#top"), Some(String::from("#top")));
+    assert_eq!(link(" "), None);
+    assert_eq!(link("page#a b"), Some(String::from("/page#a%20b")));
+
+    let options = crate::options::Options { id_prefix: String::from("p-"), ..Default::default() };
+    let link = |s: &str| PageLink::parse(s).map(|v| v.href(&options));
+    assert_eq!(link("#top"), Some(String::from("#p-top")));
+    assert_eq!(link("page#top"), Some(String::from("/page#top")));
+
+    let page = PageLink::parse(":site:cat:Page#a").unwrap();
+    assert_eq!(page, PageLink { site: Some(String::from("site")), category: Some(String::from("cat")), page: String::from("page"), anchor: Some(String::from("a")) });
+  }
+}
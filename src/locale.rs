@@ -0,0 +1,74 @@
+/// Built-in UI strings written into the output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Locale {
+  /// Label of a folded collapsible, used if `show` is not given.
+  pub collapsible_show: String,
+  /// Label of an unfolded collapsible, used if `hide` is not given.
+  pub collapsible_hide: String,
+  /// Heading of the footnote block.
+  pub footnote_block_title: String,
+  /// Heading of the table of contents.
+  pub toc_title: String,
+  /// Title of a tab written without one. `{}` is replaced by the 1-based number of the tab.
+  pub tab_fallback: String,
+}
+
+impl Locale {
+  pub fn english() -> Self {
+    Self {
+      collapsible_show: String::from("+ show block"),
+      collapsible_hide: String::from("- hide block"),
+      footnote_block_title: String::from("Footnotes"),
+      toc_title: String::from("Table of Contents"),
+      tab_fallback: String::from("Tab {}"),
+    }
+  }
+
+  pub fn japanese() -> Self {
+    Self {
+      collapsible_show: String::from("+ 開く"),
+      collapsible_hide: String::from("- 閉じる"),
+      footnote_block_title: String::from("脚注"),
+      toc_title: String::from("目次"),
+      tab_fallback: String::from("タブ {}"),
+    }
+  }
+
+  /// Bundled locale for a language tag such as `en` or `ja-JP`.
+  pub fn for_language(tag: &str) -> Option<Self> {
+    let language = tag.split(['-', '_']).next().unwrap_or("").to_lowercase();
+    match language.as_str() {
+      "en" => Some(Self::english()),
+      "ja" => Some(Self::japanese()),
+      _ => None,
+    }
+  }
+
+  pub fn tab_title(&self, number: usize) -> String {
+    self.tab_fallback.replace("{}", &number.to_string())
+  }
+}
+
+impl Default for Locale {
+  fn default() -> Self {
+    Self::english()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_for_language() {
+    assert_eq!(Locale::for_language("ja-JP"), Some(Locale::japanese()));
+    assert_eq!(Locale::for_language("EN"), Some(Locale::english()));
+    assert_eq!(Locale::for_language("fr"), None);
+  }
+
+  #[test]
+  fn test_tab_title() {
+    assert_eq!(Locale::english().tab_title(2), "Tab 2");
+    assert_eq!(Locale::japanese().tab_title(3), "タブ 3");
+  }
+}
@@ -0,0 +1,93 @@
+//! `[[module Name args]]` ... `[[/module]]`. Modules show data of the site (ratings, comments, page lists...),
+//! so they are rendered by handlers which the application registers in `Options::modules`.
+
+pub mod list_pages;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A `[[module]]` element. `[[module CSS]]` is not a `Module`, it is collected into the stylesheet of the page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Module {
+  /// Name as written, e.g. `Rate`. Handlers are looked up case-insensitively.
+  pub name: String,
+  /// `key="value"` attributes and `| key=value` arguments, in order.
+  pub args: Vec<(String, String)>,
+  /// Source between `[[module]]` and `[[/module]]`, not parsed. `None` if the module has no closing tag.
+  pub body: Option<String>,
+}
+
+impl Module {
+  /// Value of the argument `key`, e.g. `category` of `[[module ListPages category="news"]]`.
+  pub fn arg(&self, key: &str) -> Option<&str> {
+    self.args.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v.as_str())
+  }
+}
+
+/// Renders one kind of module. Closures `Fn(&Module, &Options) -> String` are handlers too.
+pub trait ModuleHandler: Send + Sync {
+  /// HTML written in place of the module. It is written as is, so escape anything taken from the page.
+  fn render(&self, module: &Module, options: &crate::options::Options) -> String;
+}
+
+impl<F: Fn(&Module, &crate::options::Options) -> String + Send + Sync> ModuleHandler for F {
+  fn render(&self, module: &Module, options: &crate::options::Options) -> String {
+    self(module, options)
+  }
+}
+
+/// Handlers by module name. Modules without a handler are left out of the output and reported as
+/// `Diagnostic::UnknownModule`.
+#[derive(Clone, Default)]
+pub struct ModuleRegistry {
+  handlers: HashMap<String, Arc<dyn ModuleHandler>>,
+}
+
+impl ModuleRegistry {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Registers the handler of the module `name`, replacing the previous one.
+  pub fn register(&mut self, name: &str, handler: impl ModuleHandler + 'static) -> &mut Self {
+    self.handlers.insert(name.to_lowercase(), Arc::new(handler));
+    self
+  }
+
+  pub fn get(&self, name: &str) -> Option<&dyn ModuleHandler> {
+    self.handlers.get(&name.to_lowercase()).map(|v| v.as_ref())
+  }
+
+  pub fn contains(&self, name: &str) -> bool {
+    self.handlers.contains_key(&name.to_lowercase())
+  }
+}
+
+impl std::fmt::Debug for ModuleRegistry {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let mut names: Vec<&String> = self.handlers.keys().collect();
+    names.sort();
+    f.debug_tuple("ModuleRegistry").field(&names).finish()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::options::Options;
+
+  fn rate() -> Module {
+    Module { name: String::from("Rate"), args: vec![(String::from("Style"), String::from("small"))], body: None }
+  }
+
+  #[test]
+  fn test_registry() {
+    let mut registry = ModuleRegistry::new();
+    registry.register("rate", |module: &Module, _: &Options| format!("<div class=\"rate\">{}</div>", module.arg("style").unwrap_or_default()));
+
+    assert!(registry.contains("RATE"));
+    assert!(!registry.contains("Comments"));
+    assert_eq!(registry.get("Rate").unwrap().render(&rate(), &Options::default()), "<div class=\"rate\">small</div>");
+    assert_eq!(format!("{:?}", registry), r#"ModuleRegistry(["rate"])"#);
+  }
+}
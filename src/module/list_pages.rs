@@ -0,0 +1,272 @@
+//! `[[module ListPages]]`: lists pages of a `PageRepository`, expanding the body of the module for each page.
+//!
+//! Arguments:
+//! - `category`: categories separated by spaces. `*` is every category, `-name` excludes one.
+//!   `.` (the default) is `_default`, as the current page is not known.
+//! - `tags`: `tag` lists pages with any of them, `+tag` pages with it and `-tag` pages without it.
+//! - `order`: `name`, `fullname`, `title`, `created_at` or `rating`, followed by `desc` to reverse. Default `created_at desc`.
+//! - `limit`: number of pages at most.
+//! - `perPage`: pages per page, 20 by default. Only the first page is rendered, as there is no request to read the page number from.
+//! - `separate`: `no` parses all pages as one piece of wikitext instead of one per page.
+//!
+//! The body may use `%%title%%`, `%%title_linked%%`, `%%name%%`, `%%fullname%%`, `%%category%%`, `%%link%%`,
+//! `%%tags%%`, `%%created_at%%`, `%%rating%%` and `%%index%%`. Without a body, `%%title_linked%%` is listed.
+
+use std::sync::Arc;
+
+use crate::module::{Module, ModuleHandler};
+use crate::options::Options;
+use crate::repository::{Page, PageRepository};
+
+const DEFAULT_TEMPLATE: &str = "%%title_linked%%";
+const DEFAULT_PER_PAGE: usize = 20;
+/// Upper limit of `perPage`, as on Wikidot.
+const MAX_PER_PAGE: usize = 250;
+
+/// The ListPages handler. Register it with `options.modules.register("ListPages", ListPages::new(repository))`.
+#[derive(Debug, Clone)]
+pub struct ListPages {
+  repository: Arc<dyn PageRepository>,
+}
+
+impl ListPages {
+  pub fn new(repository: Arc<dyn PageRepository>) -> Self {
+    Self { repository }
+  }
+
+  /// The pages listed by the module, in order.
+  pub fn select(&self, module: &Module) -> Vec<Page> {
+    let categories = module.arg("category").unwrap_or(".");
+    let tags = module.arg("tags").unwrap_or_default();
+
+    let mut pages: Vec<Page> = self.repository.pages().into_iter()
+      .filter(|page| matches_category(page, categories) && matches_tags(page, tags))
+      .collect();
+
+    let order = module.arg("order").unwrap_or("created_at desc").to_lowercase();
+    let mut order = order.split_whitespace();
+    let field = order.next().unwrap_or("created_at");
+    let desc = order.next() == Some("desc");
+    match field {
+      "name" => pages.sort_by(|a, b| a.name.cmp(&b.name)),
+      "fullname" => pages.sort_by_key(Page::full_name),
+      "title" => pages.sort_by(|a, b| a.title.cmp(&b.title)),
+      "rating" => pages.sort_by_key(|v| v.rating),
+      _ => pages.sort_by_key(|v| v.created_at),
+    }
+    // 知らない項目は既定の created_at desc で並べる
+    if desc || !["name", "fullname", "title", "created_at", "rating"].contains(&field) {
+      pages.reverse();
+    }
+
+    let per_page = module.arg("perPage").and_then(|v| v.trim().parse().ok()).unwrap_or(DEFAULT_PER_PAGE).min(MAX_PER_PAGE);
+    let limit = module.arg("limit").and_then(|v| v.trim().parse().ok()).unwrap_or(usize::MAX);
+    pages.truncate(per_page.min(limit));
+
+    pages
+  }
+}
+
+/// Names in an argument, separated by spaces or commas.
+fn split_list(s: &str) -> impl Iterator<Item = &str> {
+  s.split([' ', ',']).filter(|v| !v.is_empty())
+}
+
+fn matches_category(page: &Page, categories: &str) -> bool {
+  let category = page.category_name();
+  let mut included = false;
+  for value in split_list(categories) {
+    match value.strip_prefix('-') {
+      Some(value) if crate::link::unix_name(value) == category => { return false; }
+      Some(_) => {}
+      None => {
+        let value = if value == "." { "_default" } else { value };
+        included |= value == "*" || crate::link::unix_name(value) == category;
+      }
+    }
+  }
+  included
+}
+
+fn matches_tags(page: &Page, tags: &str) -> bool {
+  let has = |tag: &str| page.tags.iter().any(|v| v == tag);
+  let mut any_of = vec![];
+  for value in split_list(tags) {
+    if let Some(tag) = value.strip_prefix('+') {
+      if !has(tag) {
+        return false;
+      }
+    } else if let Some(tag) = value.strip_prefix('-') {
+      if has(tag) {
+        return false;
+      }
+    } else {
+      any_of.push(value);
+    }
+  }
+  any_of.is_empty() || any_of.into_iter().any(has)
+}
+
+/// Text which is shown as is, even if it looks like wikitext.
+fn escape(s: &str) -> String {
+  format!("@@{}@@", s.replace("@@", ""))
+}
+
+/// Replaces the `%%variables%%` of the template. Unknown variables are left as they are.
+fn expand(template: &str, page: &Page, index: usize, options: &Options) -> String {
+  let full_name = crate::link::unix_name(&page.full_name());
+
+  let mut res = String::new();
+  let mut rest = template;
+  while let Some(begin) = rest.find("%%") {
+    let Some(end) = rest[begin+2..].find("%%").map(|v| begin + 2 + v) else {
+      break;
+    };
+
+    let value = match &rest[begin+2..end] {
+      "title" => escape(&page.title),
+      // `]` would close the link
+      "title_linked" => format!("[[[{}|{}]]]", full_name, escape(&page.title.replace(']', ""))),
+      "name" => crate::link::unix_name(&page.name),
+      "fullname" => full_name.clone(),
+      "category" => crate::link::unix_name(page.category_name()),
+      "link" => crate::link::PageLink::parse(&full_name).map(|target| {
+        options.link_resolver.as_ref().and_then(|resolver| resolver.url(&target)).unwrap_or_else(|| target.href(options))
+      }).unwrap_or_default(),
+      "tags" => escape(&page.tags.join(" ")),
+      "created_at" => crate::repository::format_date(page.created_at),
+      "rating" => page.rating.to_string(),
+      "index" => (index + 1).to_string(),
+      _ => {
+        // `%%` の後ろから探し直す
+        res += &rest[..begin+2];
+        rest = &rest[begin+2..];
+        continue;
+      }
+    };
+    res += &rest[..begin];
+    res += &value;
+    rest = &rest[end+2..];
+  }
+  res += rest;
+
+  res
+}
+
+impl ModuleHandler for ListPages {
+  fn render(&self, module: &Module, options: &Options) -> String {
+    let template = module.body.as_deref().filter(|v| !v.trim().is_empty()).unwrap_or(DEFAULT_TEMPLATE);
+    let pages = self.select(module);
+
+    // 項目ごとに別の id を使う (見出しや脚注の id が重ならないように)
+    let item_options = |prefix: String| Options { id_prefix: options.element_id(&prefix), ..options.clone() };
+
+    let mut res = String::from(r#"<div class="list-pages-box">"#);
+    if module.arg("separate").is_some_and(|v| v.trim().eq_ignore_ascii_case("no")) {
+      let source: Vec<String> = pages.iter().enumerate().map(|(index, page)| expand(template, page, index, options)).collect();
+      res += r#"<div class="list-pages-item">"#;
+      res += &crate::parse_fragment(source.join("\n"), &item_options(String::from("list-pages-")));
+      res += "</div>";
+    } else {
+      for (index, page) in pages.iter().enumerate() {
+        res += r#"<div class="list-pages-item">"#;
+        res += &crate::parse_fragment(expand(template, page, index, options), &item_options(format!("list-pages-{index}-")));
+        res += "</div>";
+      }
+    }
+    res += "</div>";
+
+    res
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  struct Pages(Vec<Page>);
+
+  impl PageRepository for Pages {
+    fn pages(&self) -> Vec<Page> {
+      self.0.clone()
+    }
+  }
+
+  fn page(name: &str, category: Option<&str>, tags: &[&str], created_at: i64, rating: i64) -> Page {
+    Page {
+      name: String::from(name),
+      category: category.map(String::from),
+      title: name.to_uppercase(),
+      tags: tags.iter().map(|v| v.to_string()).collect(),
+      created_at,
+      rating,
+    }
+  }
+
+  fn list_pages() -> ListPages {
+    ListPages::new(Arc::new(Pages(vec![
+      page("a", None, &["x", "y"], 3, 5),
+      page("b", None, &["y"], 1, 10),
+      page("c", Some("news"), &["x"], 2, -1),
+      page("d", None, &[], 4, 0),
+    ])))
+  }
+
+  fn module(args: &[(&str, &str)], body: Option<&str>) -> Module {
+    Module {
+      name: String::from("ListPages"),
+      args: args.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+      body: body.map(String::from),
+    }
+  }
+
+  fn names(pages: Vec<Page>) -> Vec<String> {
+    pages.into_iter().map(|v| v.full_name()).collect()
+  }
+
+  #[test]
+  fn test_select() {
+    let list = list_pages();
+    assert_eq!(names(list.select(&module(&[], None))), vec!["d", "a", "b"]);
+    assert_eq!(names(list.select(&module(&[("category", "*"), ("order", "rating desc")], None))), vec!["b", "a", "d", "news:c"]);
+    assert_eq!(names(list.select(&module(&[("category", "* -news"), ("order", "title"), ("limit", "2")], None))), vec!["a", "b"]);
+    assert_eq!(names(list.select(&module(&[("category", "news _default"), ("tags", "x"), ("order", "created_at")], None))), vec!["news:c", "a"]);
+    assert_eq!(names(list.select(&module(&[("tags", "+y -x"), ("perPage", "1")], None))), vec!["b"]);
+  }
+
+  #[test]
+  fn test_expand() {
+    let options = Options::default();
+    let page = page("a", Some("news"), &["x", "y"], 1704067200, 5);
+    assert_eq!(
+      expand("%%index%%. %%title%% %%link%% %%tags%% %%created_at%% %%rating%% %%unknown%% %%fullname%%", &page, 0, &options),
+      "1. @@A@@ /news:a @@x y@@ 2024-01-01 00:00:00 5 %%unknown%% news:a",
+    );
+  }
+
+  #[test]
+  fn test_render() {
+    let mut options = Options::default();
+    options.modules.register("ListPages", list_pages());
+
+    let html = crate::parse_fragment(String::from("[[module ListPages order=\"name\" limit=\"2\"]]\n+ %%title%%\n**%%rating%%**\n[[/module]]"), &options);
+    assert_eq!(html, concat!(
+      r#"<div class="list-pages-box">"#,
+      r#"<div class="list-pages-item"><h1 id="list-pages-0-toc0">A</h1><p><strong>5</strong></p></div>"#,
+      r#"<div class="list-pages-item"><h1 id="list-pages-1-toc0">B</h1><p><strong>10</strong></p></div>"#,
+      "</div>",
+    ));
+
+    let html = crate::parse_fragment(String::from("[[module ListPages order=\"name\" separate=\"no\"]]\n[[/module]]"), &options);
+    assert_eq!(html, concat!(
+      r#"<div class="list-pages-box"><div class="list-pages-item"><p>"#,
+      r#"<a href="/a">A</a><br /><a href="/b">B</a><br /><a href="/d">D</a>"#,
+      "</p></div></div>",
+    ));
+
+    let options = Options { id_prefix: String::from("p-"), ..options };
+    let html = crate::parse_fragment(String::from("[[module ListPages order=\"name\" limit=\"2\"]]\n%%title%%[[footnote]]x[[/footnote]]\n[[/module]]"), &options);
+    assert!(html.contains(r#"id="p-list-pages-0-footnote-1""#));
+    assert!(html.contains(r#"id="p-list-pages-1-footnote-1""#));
+  }
+}
@@ -0,0 +1,20 @@
+/// Options shared by the parser and the renderer.
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+  pub sanitize: crate::sanitize::SanitizePolicy,
+  pub url: crate::url::UrlPolicy,
+  pub link: crate::link::LinkPolicy,
+  pub link_resolver: Option<std::sync::Arc<dyn crate::link::LinkResolver>>,
+  /// Handlers of `[[module]]`s.
+  pub modules: crate::module::ModuleRegistry,
+  pub locale: crate::locale::Locale,
+  /// Prepended to every generated id (footnotes, collapsibles, tabs, headings and the TOC),
+  /// so that several rendered pages can be put into one HTML document.
+  pub id_prefix: String,
+}
+
+impl Options {
+  pub fn element_id(&self, name: &str) -> String {
+    format!("{}{}", self.id_prefix, name)
+  }
+}
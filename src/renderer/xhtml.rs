@@ -1,175 +1,801 @@
 mod builder;
 
-pub fn render(ast: Vec<crate::ast::TreeElement>) -> String {
+/// Stylesheet which lets collapsibles toggle without scripts.
+/// The `<details>` element only holds the toggle, so the content can be reordered before it with flexbox.
+/// `hideLocation="both"` uses a checkbox instead, which labels above and below the content both toggle.
+const COLLAPSIBLE_STYLE: &str = "\
+.collapsible-block{display:flex;flex-direction:column}\
+.collapsible-block-link{cursor:pointer;list-style:none}\
+.collapsible-block-checkbox{position:absolute;opacity:0}\
+.collapsible-block-toggle[open] .collapsible-block-folded-label,\
+.collapsible-block-toggle:not([open]) .collapsible-block-unfolded-label,\
+.collapsible-block-toggle:not([open]) ~ .collapsible-block-content,\
+.collapsible-block-checkbox:checked ~ .collapsible-block-link .collapsible-block-folded-label,\
+.collapsible-block-checkbox:not(:checked) ~ .collapsible-block-link .collapsible-block-unfolded-label,\
+.collapsible-block-checkbox:not(:checked) ~ .collapsible-block-content{display:none}\
+.collapsible-block-hide-bottom .collapsible-block-toggle[open]{order:2}";
+
+/// Stylesheet for tabs. Each tab is a radio button, a label and the content, in this order.
+const TABVIEW_STYLE: &str = "\
+.tabview{display:flex;flex-wrap:wrap}\
+.tabview-radio{position:absolute;opacity:0}\
+.tabview-tab{cursor:pointer}\
+.tabview-content{display:none;order:1;width:100%}\
+.tabview-radio:checked + .tabview-tab + .tabview-content{display:block}";
+
+/// Visits the elements in document order. The children of an element are skipped if `f` returns `true`.
+fn visit<'a>(ast: &'a [crate::ast::TreeElement], mut f: impl FnMut(&'a crate::ast::TreeElement) -> bool) {
   use crate::ast::TreeElement;
 
-  let mut res = builder::Builder::new();
+  let mut stack: Vec<&TreeElement> = ast.iter().rev().collect();
+  while let Some(element) = stack.pop() {
+    if f(element) {
+      continue;
+    }
+    if let TreeElement::DefinitionList(items) = element {
+      for (term, definition) in items.iter().rev() {
+        stack.extend(definition.iter().rev());
+        stack.extend(term.iter().rev());
+      }
+    } else {
+      stack.extend(element.children().iter().rev());
+    }
+  }
+}
 
-  let mut unique_id_counter: usize = 0;
+/// Headings in document order, for the table of contents.
+fn collect_headings(ast: &[crate::ast::TreeElement]) -> Vec<(std::num::NonZeroUsize, String)> {
+  let mut res = vec![];
+  visit(ast, |element| match element {
+    crate::ast::TreeElement::Heading { level, .. } => {
+      res.push((*level, element.plain_text()));
+      true
+    }
+    _ => false,
+  });
+  res
+}
+
+/// Stylesheets of `[[module CSS]]` in document order.
+fn collect_stylesheets(ast: &[crate::ast::TreeElement]) -> Vec<&str> {
+  let mut res = vec![];
+  visit(ast, |element| match element {
+    crate::ast::TreeElement::Stylesheet(css) => {
+      res.push(css.as_str());
+      true
+    }
+    _ => false,
+  });
+  res
+}
+
+/// Built-in stylesheets needed by the page. Pages without collapsibles or tabs get none.
+fn builtin_styles(ast: &[crate::ast::TreeElement]) -> Vec<&'static str> {
+  use crate::ast::TreeElement;
+
+  let (mut collapsible, mut tabview) = (false, false);
+  visit(ast, |element| {
+    match element {
+      TreeElement::Collapsible { .. } => { collapsible = true; }
+      TreeElement::TabView(_) | TreeElement::Tab { .. } => { tabview = true; }
+      _ => {}
+    }
+    collapsible && tabview
+  });
+
+  let mut res = vec![];
+  if collapsible {
+    res.push(COLLAPSIBLE_STYLE);
+  }
+  if tabview {
+    res.push(TABVIEW_STYLE);
+  }
+  res
+}
+
+pub fn render(ast: Vec<crate::ast::TreeElement>, options: &crate::options::Options) -> String {
+  let mut res = builder::Builder::new();
 
   res.open(String::from("html"), vec![]);
   res.open(String::from("head"), vec![]);
   res.insert(String::from("meta"), vec![("charset", "UTF-8")]);
   res.insert(String::from("meta"), vec![("name", "viewport"), ("content", "width=device-width, initial-scale=1")]);
+  let styles = builtin_styles(&ast);
+  if !styles.is_empty() {
+    res.open(String::from("style"), vec![]);
+    res.write_raw(&styles.concat());
+    res.close();
+  }
+  let stylesheets = collect_stylesheets(&ast);
+  if !stylesheets.is_empty() {
+    res.open(String::from("style"), vec![]);
+    res.write_raw(&stylesheets.join("\n"));
+    res.close();
+  }
   res.close(); // </head>
   res.open(String::from("body"), vec![]);
+  render_body(&mut res, ast, options);
+  res.close(); // </body>
+  res.close(); // </html>
+
+  res.into()
+}
 
-  let mut iters = vec![Some(ast.into_iter())];
+/// Renders only the content of `<body>`, e.g. for content embedded in another page.
+/// Stylesheets of `[[module CSS]]` are left out, as they belong in `<head>`.
+pub fn render_fragment(ast: Vec<crate::ast::TreeElement>, options: &crate::options::Options) -> String {
+  let mut res = builder::Builder::new();
+  render_body(&mut res, ast, options);
+  res.into()
+}
 
-  while !iters.is_empty() {
-    if let Some(i) = iters.last_mut().unwrap() {
-      if let Some(v) = i.next() {
-        match v {
-          TreeElement::Paragraph(children) => {
-            res.open(String::from("p"), vec![]);
-            iters.push(Some(children.into_iter()));
-          }
+fn render_body(res: &mut builder::Builder, ast: Vec<crate::ast::TreeElement>, options: &crate::options::Options) {
+  use crate::ast::TreeElement;
+  use crate::constants;
 
-          TreeElement::Text(text) => {
-            res.write(&text);
-          }
+  let mut unique_id_counter: usize = 0;
+  let mut heading_counter: usize = 0;
+  let mut tabview_counter: usize = 0;
+  // (depth of the TabView's children in `iters`, id of the TabView, number of Tabs rendered)
+  let mut tabviews: Vec<(usize, String, usize)> = vec![];
+  // (depth of the content of a `hideLocation="both"` collapsible in `iters`, id of its checkbox, "hide" label)
+  let mut collapsibles: Vec<(usize, String, String)> = vec![];
+  // (depth of the list in `iters`, remaining items, definition of the open term)
+  let mut definition_lists: Vec<(usize, std::vec::IntoIter<crate::ast::DefinitionItem>, Option<Vec<TreeElement>>)> = vec![];
 
-          TreeElement::Bold(children) => {
-            res.open(String::from("strong"), vec![]);
-            iters.push(Some(children.into_iter()));
-          }
+  let headings = collect_headings(&ast);
 
-          TreeElement::Italics(children) => {
-            res.open(String::from("i"), vec![]);
-            iters.push(Some(children.into_iter()));
-          }
+  // each iterator remembers how many tags to close once it is exhausted
+  let mut iters = vec![(ast.into_iter(), 0)];
 
-          TreeElement::Underline(children) => {
-            res.open(String::from("span"), vec![("style", "text-decoration: underline")]);
-            iters.push(Some(children.into_iter()));
-          }
+  loop {
+    // 定義リストの項目は dt, dd の順に一つずつ開く
+    if let Some((depth, items, definition)) = definition_lists.last_mut() && *depth == iters.len() {
+      if let Some(definition) = definition.take() {
+        res.open(String::from("dd"), vec![]);
+        iters.push((definition.into_iter(), 1));
+      } else if let Some((term, next_definition)) = items.next() {
+        res.open(String::from("dt"), vec![]);
+        iters.push((term.into_iter(), 1));
+        *definition = Some(next_definition);
+      } else {
+        res.close();
+        definition_lists.pop();
+      }
+      continue;
+    }
 
-          TreeElement::Strikethrough(children) => {
-            res.open(String::from("s"), vec![]);
-            iters.push(Some(children.into_iter()));
-          }
+    let Some((i, _)) = iters.last_mut() else { break };
+    if let Some(v) = i.next() {
+      match v {
+        TreeElement::Paragraph(children) => {
+          res.open(String::from("p"), vec![]);
+          iters.push((children.into_iter(), 1));
+        }
 
-          TreeElement::Monospaced(children) => {
-            res.open(String::from("code"), vec![]);
-            iters.push(Some(children.into_iter()));
-          }
+        TreeElement::Text(text) => {
+          res.write(&text);
+        }
 
-          TreeElement::Superscript(children) => {
-            res.open(String::from("sup"), vec![]);
-            iters.push(Some(children.into_iter()));
-          }
+        TreeElement::Bold(children) => {
+          res.open(String::from("strong"), vec![]);
+          iters.push((children.into_iter(), 1));
+        }
 
-          TreeElement::Subscript(children) => {
-            res.open(String::from("sub"), vec![]);
-            iters.push(Some(children.into_iter()));
-          }
+        TreeElement::Italics(children) => {
+          res.open(String::from("i"), vec![]);
+          iters.push((children.into_iter(), 1));
+        }
 
-          TreeElement::Colored{red, green, blue, children} => {
-            res.open(String::from("span"), vec![("style", &format!("color: rgb({}, {}, {})", red, green, blue))]);
-            iters.push(Some(children.into_iter()));
-          }
+        TreeElement::Underline(children) => {
+          res.open(String::from("span"), vec![("style", "text-decoration: underline")]);
+          iters.push((children.into_iter(), 1));
+        }
 
-          TreeElement::Size { scale, children } => {
-            res.open(String::from("span"), vec![("style", &format!("font-size: {}", scale.0.as_str()))]);
-            iters.push(Some(children.into_iter()));
-          }
+        TreeElement::Strikethrough(children) => {
+          res.open(String::from("s"), vec![]);
+          iters.push((children.into_iter(), 1));
+        }
 
-          TreeElement::Link { href, open_in_new_tab, name } => {
-            let mut attrs = vec![("href", &href.0 as &str)];
-            if open_in_new_tab {
-              attrs.push(("target", "_blank"));
-              attrs.push(("rel", "noopener noreferrer"));
-            }
-            res.open(String::from("a"), attrs);
-            res.write(&name);
-            res.close()
+        TreeElement::Monospaced(children) => {
+          res.open(String::from("code"), vec![]);
+          iters.push((children.into_iter(), 1));
+        }
+
+        TreeElement::Superscript(children) => {
+          res.open(String::from("sup"), vec![]);
+          iters.push((children.into_iter(), 1));
+        }
+
+        TreeElement::Subscript(children) => {
+          res.open(String::from("sub"), vec![]);
+          iters.push((children.into_iter(), 1));
+        }
+
+        TreeElement::Colored{red, green, blue, children} => {
+          res.open(String::from("span"), vec![("style", &format!("color: rgb({}, {}, {})", red, green, blue))]);
+          iters.push((children.into_iter(), 1));
+        }
+
+        TreeElement::Size { scale, children } => {
+          res.open(String::from("span"), vec![("style", &format!("font-size: {}", scale.0.as_str()))]);
+          iters.push((children.into_iter(), 1));
+        }
+
+        TreeElement::Link { href, open_in_new_tab, missing, children } => {
+          let rel = options.link.rel(&href, &options.url, open_in_new_tab);
+          let mut attrs = vec![("href", href.as_str())];
+          if missing {
+            attrs.push(("class", "newpage"));
           }
+          if open_in_new_tab {
+            attrs.push(("target", "_blank"));
+          }
+          if let Some(rel) = &rel {
+            attrs.push(("rel", rel));
+          }
+          res.open(String::from("a"), attrs);
+          iters.push((children.into_iter(), 1));
+        }
 
-          TreeElement::Collapsible{text_open, text_closed, children} => {
-            let open_id = format!("collapsible_open{}", unique_id_counter.to_string());
-            let close_id = format!("collapsible_close{}", unique_id_counter.to_string());
+        TreeElement::Collapsible{text_open, text_closed, folded, hide_location, children} => {
+          use crate::ast::HideLocation;
 
-            res.open("div".to_string(), vec![]);
-              res.open("div".to_string(), vec![("id", &open_id)]);
-                res.insert("a".to_string(), vec![("onclick", &format!(r##"()=>{{document.querySelector("#{}").style.display="none"; document.querySelector("#{}").style.display="block";}}"##, &close_id, &open_id))]);
+          let id = options.element_id(&format!("{}{}", constants::COLLAPSIBLE_ID_PREFIX, unique_id_counter));
+          let class = match hide_location {
+            HideLocation::Top => "collapsible-block collapsible-block-hide-top",
+            HideLocation::Bottom => "collapsible-block collapsible-block-hide-bottom",
+            HideLocation::Both => "collapsible-block collapsible-block-hide-both",
+          };
+
+          res.open("div".to_string(), vec![("class", class)]);
+          if hide_location == HideLocation::Both {
+            // <details> は下から閉じられないので、チェックボックスと上下の <label for> で開閉する
+            let mut checkbox_attrs = vec![("type", "checkbox"), ("class", "collapsible-block-checkbox"), ("id", id.as_str())];
+            if !folded {
+              checkbox_attrs.push(("checked", "checked"));
+            }
+            res.insert("input".to_string(), checkbox_attrs);
+            res.open("label".to_string(), vec![("class", "collapsible-block-link"), ("for", id.as_str())]);
+              res.open("span".to_string(), vec![("class", "collapsible-block-folded-label")]);
+                res.write(&text_open);
               res.close();
-              res.open("div".to_string(), vec![("id", &close_id)]);
-                res.insert("a".to_string(), vec![("onclick", &format!(r##"()=>{{document.querySelector("#{}").style.display="none"; document.querySelector("#{}").style.display="block";}}"##, &open_id, &close_id))]);
-              iters.push(Some(children.into_iter()));
-            iters.push(None);
+              res.open("span".to_string(), vec![("class", "collapsible-block-unfolded-label")]);
+                res.write(&text_closed);
+              res.close();
+            res.close();
+            res.open("div".to_string(), vec![("class", "collapsible-block-content")]);
+            iters.push((children.into_iter(), 1));
+            // 下のラベルは中身を書き終えてから書く
+            collapsibles.push((iters.len(), id, text_closed));
             unique_id_counter+=1;
+            continue;
           }
-
-          TreeElement::Footnote(id) => {
-            res.open("sup".to_string(), vec![]);
-              res.open("a".to_string(), vec![("href", &format!("#{}{}", crate::constants::FOOTNOTE_ID_PREFIX, id.get()))]);
+            let mut details_attrs = vec![("class", "collapsible-block-toggle"), ("id", id.as_str())];
+            if !folded {
+              details_attrs.push(("open", "open"));
+            }
+            res.open("details".to_string(), details_attrs);
+              res.open("summary".to_string(), vec![("class", "collapsible-block-link")]);
+                res.open("span".to_string(), vec![("class", "collapsible-block-folded-label")]);
+                  res.write(&text_open);
+                res.close();
+                res.open("span".to_string(), vec![("class", "collapsible-block-unfolded-label")]);
+                  res.write(&text_closed);
+                res.close();
               res.close();
             res.close();
-          }
+            res.open("div".to_string(), vec![("class", "collapsible-block-content")]);
+          iters.push((children.into_iter(), 2));
+          unique_id_counter+=1;
+        }
 
-          TreeElement::FootnoteTarget(children) => {
-            res.open("div".to_string(), vec![("class", "footnoteblock")]);
-            iters.push(Some(children.into_iter()));
-          }
+        TreeElement::Anchor(name) => {
+          res.open(String::from("a"), vec![("id", &options.element_id(&name))]);
+          res.close();
+        }
 
-          TreeElement::FootnoteTargetChild { id, children } => {
-            res.open("div".to_string(), vec![("id", &format!("{}{}", crate::constants::FOOTNOTE_ID_PREFIX, id.get()))]);
-            res.write(&format!("{}. ", id.get())); // e.g. "1. some footnote"
-            iters.push(Some(children.into_iter()));
-          }
+        TreeElement::Footnote(id) => {
+          res.open("sup".to_string(), vec![]);
+            res.open("a".to_string(), vec![("href", &format!("#{}", options.element_id(&format!("{}{}", constants::FOOTNOTE_ID_PREFIX, id.get()))))]);
+              res.write(&id.to_string());
+            res.close();
+          res.close();
+        }
 
-          TreeElement::QuoteBlock(children) => {
-            res.open(String::from("blockquote"), vec![]);
-            iters.push(Some(children.into_iter()));
-          }
+        TreeElement::FootnoteTarget(children) => {
+          res.open("div".to_string(), vec![("class", "footnoteblock")]);
+            res.open("div".to_string(), vec![("class", "title")]);
+              res.write(&options.locale.footnote_block_title);
+            res.close();
+          iters.push((children.into_iter(), 1));
+        }
+
+        TreeElement::FootnoteTargetChild { id, children } => {
+          res.open("div".to_string(), vec![("id", &options.element_id(&format!("{}{}", constants::FOOTNOTE_ID_PREFIX, id.get())))]);
+          res.write(&format!("{}. ", id.get())); // e.g. "1. some footnote"
+          iters.push((children.into_iter(), 1));
+        }
+
+        TreeElement::QuoteBlock(children) => {
+          res.open(String::from("blockquote"), vec![]);
+          iters.push((children.into_iter(), 1));
+        }
+
+        TreeElement::Iframe(raw) => { // TODO size?
+          res.open(String::from("iframe"), vec![("srcdoc", &raw)]);
+          res.close();
+        }
+
+        TreeElement::ExternalIframe { src } => {
+          res.open(String::from("iframe"), vec![("src", src.as_str()), ("sandbox", "allow-scripts allow-popups")]);
+          res.close();
+        }
+
+        TreeElement::Image { src, alt } => {
+          res.insert(String::from("img"), vec![("src", src.as_str()), ("alt", &alt)]);
+        }
 
-          TreeElement::Iframe(raw) => { // TODO size?
-            res.open(String::from("iframe"), vec![("srcdoc", &raw)]);
+        TreeElement::Tab{title, children} => {
+          if let Some((depth, view_id, count)) = tabviews.last_mut() && *depth == iters.len() {
+            let tab_id = format!("{}-{}", view_id, count);
+            let button_id = format!("{}-button", tab_id);
+
+            let mut radio_attrs = vec![("type", "radio"), ("class", "tabview-radio"), ("name", view_id.as_str()), ("id", button_id.as_str())];
+            if *count == 0 {
+              radio_attrs.push(("checked", "checked"));
+            }
+            res.insert(String::from("input"), radio_attrs);
+            res.open(String::from("label"), vec![("class", "tabview-tab"), ("for", &button_id)]);
+              if title.trim().is_empty() {
+                res.write(&options.locale.tab_title(*count + 1));
+              } else {
+                res.write(&title);
+              }
             res.close();
+            res.open(String::from("div"), vec![("class", "tabview-content"), ("id", &tab_id)]);
+            *count += 1;
+          } else { // Tab outside of TabView
+            res.open(String::from("div"), vec![("class", "tabview-content")]);
           }
+          iters.push((children.into_iter(), 1));
+        }
 
-          TreeElement::Tab{title, children} => {
-            todo!()
-          }
+        TreeElement::TabView(children) => {
+          let id = options.element_id(&format!("{}{}", constants::TABVIEW_ID_PREFIX, tabview_counter));
+          res.open(String::from("div"), vec![("class", "tabview"), ("id", &id)]);
+          iters.push((children.into_iter(), 1));
+          tabviews.push((iters.len(), id, 0));
+          tabview_counter += 1;
+        }
 
-          TreeElement::TabView(_) => {
-            todo!()
-          }
+        TreeElement::Heading { level, children } => {
+          let id = options.element_id(&format!("{}{}", constants::HEADING_ID_PREFIX, heading_counter));
+          res.open(format!("h{}", level.get().min(6)), vec![("id", &id)]);
+          iters.push((children.into_iter(), 1));
+          heading_counter += 1;
+        }
 
-          TreeElement::Table(rows) => {
-            res.open(String::from("table"), vec![]);
-            for row in rows {
-              res.open(String::from("tr"), vec![]);
-              for cell in row {
-                todo!();
+        TreeElement::TableOfContents => {
+          res.open(String::from("div"), vec![("id", &options.element_id(constants::TOC_ID)), ("class", "toc")]);
+            res.open(String::from("div"), vec![("class", "title")]);
+              res.write(&options.locale.toc_title);
+            res.close();
+            res.open(String::from("div"), vec![("class", "toc-list")]);
+              for (at, (level, text)) in headings.iter().enumerate() {
+                let href = format!("#{}", options.element_id(&format!("{}{}", constants::HEADING_ID_PREFIX, at)));
+                res.open(String::from("div"), vec![("style", &format!("margin-left: {}em", level.get()))]);
+                  res.open(String::from("a"), vec![("href", &href)]);
+                    res.write(text);
+                  res.close();
+                res.close();
               }
-              res.close();
-            }
             res.close();
+          res.close();
+        }
+
+        TreeElement::Align { alignment, children } => {
+          res.open(String::from("div"), vec![("style", &format!("text-align: {}", alignment.as_css()))]);
+          iters.push((children.into_iter(), 1));
+        }
+
+        TreeElement::Table { attributes, children } => {
+          res.open(String::from("table"), attributes.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect());
+          iters.push((children.into_iter(), 1));
+        }
+
+        TreeElement::TableRow { attributes, children } => {
+          res.open(String::from("tr"), attributes.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect());
+          iters.push((children.into_iter(), 1));
+        }
+
+        TreeElement::TableCell { style, colspan, rowspan, mut attributes, children } => {
+          use crate::ast::table_cell::Style;
+
+          let align = match style {
+            Some(Style::LeftAligned) => Some("left"),
+            Some(Style::RightAligned) => Some("right"),
+            Some(Style::CenterAligned) => Some("center"),
+            Some(Style::Title) | None => None,
+          };
+          if let Some(align) = align {
+            match attributes.iter_mut().find(|(k, _)| k == "style") {
+              Some((_, value)) => { *value = format!("text-align: {}; {}", align, value); }
+              None => { attributes.push((String::from("style"), format!("text-align: {}", align))); }
+            }
+          }
+          if colspan.get() > 1 {
+            attributes.push((String::from("colspan"), colspan.to_string()));
           }
+          if rowspan.get() > 1 {
+            attributes.push((String::from("rowspan"), rowspan.to_string()));
+          }
+
+          let tag = if style == Some(Style::Title) { "th" } else { "td" };
+          res.open(String::from(tag), attributes.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect());
+          iters.push((children.into_iter(), 1));
+        }
 
-          TreeElement::NewLine => {
-            res.insert(String::from("br"), vec![]);
+        TreeElement::DefinitionList(items) => {
+          res.open(String::from("dl"), vec![]);
+          definition_lists.push((iters.len(), items.into_iter(), None));
+        }
+
+        TreeElement::NewLine => {
+          res.insert(String::from("br"), vec![]);
+        }
+
+        TreeElement::HorizontalRule => {
+          res.insert(String::from("hr"), vec![]);
+        }
+
+        TreeElement::Stylesheet(_) => {
+          // already written in <head>
+        }
+
+        TreeElement::Module(module) => {
+          // 知らないモジュールは書かない (Diagnostic::UnknownModule)
+          if let Some(handler) = options.modules.get(&module.name) {
+            res.write_raw(&handler.render(&module, options));
           }
+        }
 
-          TreeElement::HtmlElement { tag, property, children } => {
-            let attrs: Vec<(String, String)> = property.iter().map(|(k, v)| (k.clone(), v.replace("\n", "").replace("\"", ""))).collect();
-            let attrs_refs: Vec<(&str, &str)> = attrs.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
-            res.open(tag, attrs_refs);
-            iters.push(Some(children.into_iter()));
+        TreeElement::HtmlElement { tag, property, children } => {
+          if !options.sanitize.is_tag_allowed(&tag) {
+            iters.push((children.into_iter(), 0));
+            continue;
           }
+
+          let attrs: Vec<(String, String)> = property.iter().map(|(k, v)| (k.clone(), v.replace("\n", "").replace("\"", ""))).collect();
+          let attrs_refs: Vec<(&str, &str)> = attrs.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+          res.open(tag, attrs_refs);
+          iters.push((children.into_iter(), 1));
         }
-      } else {
-        if iters.len() > 1 {res.close()}; // don't close root
-        iters.pop();
       }
-    } else {
-      iters.pop();
+    } else if let Some((_, close_count)) = iters.pop() {
+      for _ in 0..close_count {
+        res.close();
+      }
+      while tabviews.last().is_some_and(|(depth, ..)| *depth > iters.len()) {
+        tabviews.pop();
+      }
+      if let Some((depth, ..)) = collapsibles.last() && *depth > iters.len() && let Some((_, id, text_closed)) = collapsibles.pop() {
+        res.open("label".to_string(), vec![("class", "collapsible-block-link collapsible-block-bottom-link"), ("for", id.as_str())]);
+          res.open("span".to_string(), vec![("class", "collapsible-block-unfolded-label")]);
+            res.write(&text_closed);
+          res.close();
+        res.close();
+        res.close(); // collapsible-block
+      }
     }
   }
 
-  res.close(); // </body>
-  res.close(); // </html>
+}
 
-  res.into()
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::ast::{HideLocation, TreeElement};
+  use crate::options::Options;
+
+  fn collapsible(folded: bool, hide_location: HideLocation) -> Vec<TreeElement> {
+    vec![TreeElement::Collapsible {
+      text_open: String::from("+ open"),
+      text_closed: String::from("- close"),
+      folded,
+      hide_location,
+      children: vec![TreeElement::Text(String::from("body"))],
+    }]
+  }
+
+  #[test]
+  fn test_table() {
+    use crate::ast::table_cell::Style;
+
+    let nz = |v: usize| std::num::NonZeroUsize::try_from(v).unwrap();
+    let html = render(vec![TreeElement::Table { attributes: vec![(String::from("class"), String::from("wiki"))], children: vec![
+      TreeElement::TableRow { attributes: vec![], children: vec![
+        TreeElement::TableCell { style: Some(Style::Title), colspan: nz(2), rowspan: nz(1), attributes: vec![], children: vec![TreeElement::Text(String::from("a"))] },
+      ]},
+      TreeElement::TableRow { attributes: vec![], children: vec![
+        TreeElement::TableCell { style: Some(Style::CenterAligned), colspan: nz(1), rowspan: nz(3), attributes: vec![(String::from("style"), String::from("color: red"))], children: vec![TreeElement::Text(String::from("b"))] },
+        TreeElement::TableCell { style: None, colspan: nz(1), rowspan: nz(1), attributes: vec![], children: vec![TreeElement::Text(String::from("c"))] },
+      ]},
+    ]}], &Options::default());
+    assert!(html.contains(r#"<table class="wiki"><tr><th colspan="2">a</th></tr><tr><td style="text-align: center; color: red" rowspan="3">b</td><td>c</td></tr></table>"#));
+  }
+
+  #[test]
+  fn test_definition_list() {
+    let html = render(vec![TreeElement::DefinitionList(vec![
+      (vec![TreeElement::Text(String::from("a"))], vec![TreeElement::Bold(vec![TreeElement::Text(String::from("b"))])]),
+      (vec![TreeElement::Text(String::from("c"))], vec![]),
+    ]), TreeElement::Text(String::from("d"))], &Options::default());
+    assert!(html.contains("<body><dl><dt>a</dt><dd><strong>b</strong></dd><dt>c</dt><dd></dd></dl>d</body>"));
+  }
+
+  #[test]
+  fn test_collapsible_without_script() {
+    let html = render(collapsible(true, HideLocation::Top), &Options::default());
+    assert!(!html.contains("onclick"));
+    assert!(html.contains(r#"<details class="collapsible-block-toggle" id="collapsible-0"><summary class="collapsible-block-link"><span class="collapsible-block-folded-label">+ open</span><span class="collapsible-block-unfolded-label">- close</span></summary></details>"#));
+    assert!(html.contains(r#"<div class="collapsible-block-content">body</div></div></body>"#));
+  }
+
+  #[test]
+  fn test_collapsible_unfolded() {
+    let html = render(collapsible(false, HideLocation::Bottom), &Options::default());
+    assert!(html.contains(r#"<div class="collapsible-block collapsible-block-hide-bottom"><details class="collapsible-block-toggle" id="collapsible-0" open="open">"#));
+    assert!(!html.contains("collapsible-block-unfolded-link\""));
+  }
+
+  #[test]
+  fn test_collapsible_hide_both() {
+    let html = render(collapsible(true, HideLocation::Both), &Options::default());
+    assert!(html.contains(concat!(
+      r#"<div class="collapsible-block collapsible-block-hide-both"><input type="checkbox" class="collapsible-block-checkbox" id="collapsible-0" />"#,
+      r#"<label class="collapsible-block-link" for="collapsible-0"><span class="collapsible-block-folded-label">+ open</span><span class="collapsible-block-unfolded-label">- close</span></label>"#,
+      r#"<div class="collapsible-block-content">body</div>"#,
+      r#"<label class="collapsible-block-link collapsible-block-bottom-link" for="collapsible-0"><span class="collapsible-block-unfolded-label">- close</span></label></div>"#,
+    )));
+    assert!(!html.contains("<a ") && !html.contains("<details"));
+
+    // 入れ子でも下のラベルはそれぞれの中身の後に来る
+    let mut outer = collapsible(false, HideLocation::Both);
+    if let TreeElement::Collapsible { children, .. } = &mut outer[0] {
+      children.extend(collapsible(false, HideLocation::Both));
+      children.push(TreeElement::Text(String::from("tail")));
+    }
+    let html = render(outer, &Options::default());
+    assert!(html.contains(r#"id="collapsible-0" checked="checked" />"#));
+    assert!(html.contains(r#"for="collapsible-1"><span class="collapsible-block-unfolded-label">- close</span></label></div>tail</div><label class="collapsible-block-link collapsible-block-bottom-link" for="collapsible-0">"#));
+  }
+
+  #[test]
+  fn test_builtin_styles() {
+    let html = render(vec![TreeElement::Paragraph(vec![TreeElement::Text(String::from("a"))])], &Options::default());
+    assert!(!html.contains("<style>"));
+
+    let html = render(vec![TreeElement::DefinitionList(vec![(vec![], collapsible(true, HideLocation::Top))])], &Options::default());
+    assert!(html.contains(&format!("<style>{}</style></head>", COLLAPSIBLE_STYLE)));
+
+    let html = render(vec![TreeElement::TabView(vec![])], &Options::default());
+    assert!(html.contains(&format!("<style>{}</style></head>", TABVIEW_STYLE)));
+  }
+
+  #[test]
+  fn test_disallowed_tag_keeps_children() {
+    let mut options = Options::default();
+    options.sanitize.allowed_tags.remove("div");
+    let html = render(vec![TreeElement::HtmlElement {
+      tag: String::from("div"),
+      property: vec![(String::from("class"), String::from("box"))],
+      children: vec![TreeElement::Text(String::from("inner"))],
+    }], &options);
+    assert!(html.ends_with("<body>inner</body></html>"));
+  }
+
+  fn nz(v: usize) -> std::num::NonZeroUsize {
+    std::num::NonZeroUsize::try_from(v).unwrap()
+  }
+
+  #[test]
+  fn test_id_prefix() {
+    let ast = vec![
+      TreeElement::Heading { level: nz(1), children: vec![TreeElement::Text(String::from("Title"))] },
+      TreeElement::Collapsible {
+        text_open: String::from("+"),
+        text_closed: String::from("-"),
+        folded: true,
+        hide_location: HideLocation::Both,
+        children: vec![],
+      },
+      TreeElement::Footnote(nz(1)),
+      TreeElement::Anchor(String::from("top")),
+      TreeElement::FootnoteTargetChild { id: nz(1), children: vec![] },
+      TreeElement::TabView(vec![TreeElement::Tab { title: String::from("A"), children: vec![] }]),
+      TreeElement::TableOfContents,
+    ];
+    let options = Options { id_prefix: String::from("page1-"), ..Options::default() };
+    let html = render(ast, &options);
+
+    for id in ["toc0", "collapsible-0", "footnote-1", "top", "wiki-tab-0", "wiki-tab-0-0", "wiki-tab-0-0-button", "toc"] {
+      assert!(html.contains(&format!(r#"id="page1-{}""#, id)), "{} is not prefixed", id);
+      assert!(!html.contains(&format!(r#"id="{}""#, id)), "{} is not prefixed", id);
+    }
+    for href in ["toc0", "footnote-1"] {
+      assert!(html.contains(&format!(r##"href="#page1-{}""##, href)), "{} is not prefixed", href);
+    }
+  }
+
+  #[test]
+  fn test_tabview() {
+    let html = render(vec![
+      TreeElement::TabView(vec![
+        TreeElement::Tab { title: String::from("A"), children: vec![TreeElement::Text(String::from("a"))] },
+        TreeElement::Tab { title: String::from("B"), children: vec![TreeElement::Text(String::from("b"))] },
+      ]),
+    ], &Options::default());
+    assert!(html.contains(concat!(
+      r#"<div class="tabview" id="wiki-tab-0">"#,
+      r#"<input type="radio" class="tabview-radio" name="wiki-tab-0" id="wiki-tab-0-0-button" checked="checked" />"#,
+      r#"<label class="tabview-tab" for="wiki-tab-0-0-button">A</label>"#,
+      r#"<div class="tabview-content" id="wiki-tab-0-0">a</div>"#,
+      r#"<input type="radio" class="tabview-radio" name="wiki-tab-0" id="wiki-tab-0-1-button" />"#,
+      r#"<label class="tabview-tab" for="wiki-tab-0-1-button">B</label>"#,
+      r#"<div class="tabview-content" id="wiki-tab-0-1">b</div>"#,
+      r#"</div>"#,
+    )));
+  }
+
+  #[test]
+  fn test_table_of_contents() {
+    let html = render(vec![
+      TreeElement::TableOfContents,
+      TreeElement::Heading { level: nz(1), children: vec![TreeElement::Text(String::from("One"))] },
+      TreeElement::Heading { level: nz(2), children: vec![TreeElement::Bold(vec![TreeElement::Text(String::from("Two"))])] },
+    ], &Options::default());
+    assert!(html.contains(r##"<div style="margin-left: 1em"><a href="#toc0">One</a></div><div style="margin-left: 2em"><a href="#toc1">Two</a></div>"##));
+    assert!(html.contains(r#"<h2 id="toc1"><strong>Two</strong></h2>"#));
+  }
+
+  #[test]
+  fn test_locale() {
+    let options = Options { locale: crate::locale::Locale::japanese(), ..Options::default() };
+    let html = render(vec![
+      TreeElement::TableOfContents,
+      TreeElement::TabView(vec![
+        TreeElement::Tab { title: String::new(), children: vec![] },
+      ]),
+      TreeElement::FootnoteTarget(vec![]),
+    ], &options);
+    assert!(html.contains(r#"<div class="title">目次</div>"#));
+    assert!(html.contains(r#"<label class="tabview-tab" for="wiki-tab-0-0-button">タブ 1</label>"#));
+    assert!(html.contains(r#"<div class="footnoteblock"><div class="title">脚注</div></div>"#));
+  }
+
+  #[test]
+  fn test_link_rel() {
+    let href = crate::url::Url::parse("https://example.com/", &crate::url::UrlPolicy::default()).unwrap();
+    let link = vec![TreeElement::Link { href, open_in_new_tab: true, missing: false, children: vec![TreeElement::Text(String::from("a"))] }];
+
+    let html = render(link.clone(), &Options::default());
+    assert!(html.contains(r#"<a href="https://example.com/" target="_blank" rel="noopener noreferrer">a</a>"#));
+
+    let options = Options { link: crate::link::LinkPolicy { nofollow_external: true, ..Default::default() }, ..Options::default() };
+    let html = render(link, &options);
+    assert!(html.contains(r#"rel="nofollow ugc noopener noreferrer""#));
+  }
+
+  #[test]
+  fn test_missing_page_link() {
+    let href = crate::url::Url::parse("/new-page", &crate::url::UrlPolicy::default()).unwrap();
+    let html = render(vec![TreeElement::Link { href, open_in_new_tab: false, missing: true, children: vec![TreeElement::Text(String::from("a"))] }], &Options::default());
+    assert!(html.contains(r#"<a href="/new-page" class="newpage">a</a>"#));
+  }
+
+  #[test]
+  fn test_horizontal_rule() {
+    let html = render(vec![
+      TreeElement::Paragraph(vec![TreeElement::Text(String::from("a"))]),
+      TreeElement::HorizontalRule,
+    ], &Options::default());
+    assert!(html.contains("<p>a</p><hr />"));
+  }
+
+  #[test]
+  fn test_stylesheet_for_document() {
+    let html = crate::parse(String::from("[[module CSS]]\nbody, * { display: none }\n[[/module]]\na"));
+    assert!(!html.contains("display"));
+  }
+
+  #[test]
+  fn test_stylesheet() {
+    let html = crate::parse(String::from("[[module CSS]]\n.a { color: red; --x: 1 }\n[[/module]]\n> [[module css]]b{width:1px}[[/module]]\n**c**"));
+    assert!(html.contains(" /><style>.a { color: red }\nb { width: 1px }</style></head>"));
+    assert!(html.contains("<body><blockquote></blockquote><p><strong>c</strong></p></body>"));
+  }
+
+  #[test]
+  fn test_module() {
+    let mut options = Options::default();
+    options.modules.register("Redirect", |module: &crate::module::Module, _: &Options| {
+      format!("<div class=\"redirect\">{}</div>", module.arg("destination").unwrap_or_default())
+    });
+
+    let (html, diagnostics) = crate::parse_with_diagnostics(String::from("[[module redirect destination=\"x\"]]\n[[module Rate]]\n[[module Comments]]\nbody\n[[/module]]"), &options);
+    assert!(html.contains(r#"<body><div class="redirect">x</div></body>"#));
+    assert_eq!(diagnostics, vec![
+      crate::diagnostic::Diagnostic::UnknownModule { name: String::from("Rate"), position: 36 },
+      crate::diagnostic::Diagnostic::UnknownModule { name: String::from("Comments"), position: 52 },
+    ]);
+  }
+
+  #[test]
+  fn test_module_in_line() {
+    let mut options = Options::default();
+    options.modules.register("Rate", |_: &crate::module::Module, _: &Options| String::from("<span class=\"rate\">+1</span>"));
+
+    let html = crate::parse_fragment(String::from("|| a [[module Rate]] b || c ||\nd [[module Rate]] e"), &options);
+    assert_eq!(html, r#"<table><tr><td> a <span class="rate">+1</span> b </td><td> c </td></tr></table><p>d <span class="rate">+1</span> e</p>"#);
+
+    let (_, diagnostics) = crate::parse_with_diagnostics(String::from("[[a href=\"http://x\"]]b [[module Comments]][[/a]]"), &Options::default());
+    assert_eq!(diagnostics, vec![crate::diagnostic::Diagnostic::UnknownModule { name: String::from("Comments"), position: 23 }]);
+  }
+
+  #[test]
+  fn test_include() {
+    let (html, diagnostics) = crate::parse_with_diagnostics(String::from("a [[include page | a=1]] b"), &Options::default());
+    assert!(html.contains("<body><p>a  b</p></body>"));
+    assert_eq!(diagnostics, vec![crate::diagnostic::Diagnostic::UnsupportedInclude { page: String::from("page"), position: 2 }]);
+  }
+
+  #[test]
+  fn test_backslash_at_end() {
+    // 末尾の `\` で panic しない
+    assert_eq!(crate::parse_fragment(String::from("a\\"), &Options::default()), "<p>a\n</p>");
+    assert_eq!(crate::parse_fragment(String::from("\\"), &Options::default()), "<p>\n</p>");
+  }
+
+  #[test]
+  fn test_align() {
+    let html = crate::parse(String::from("[[>]]\na\n\nb\n[[/>]]"));
+    assert!(html.contains(r#"<div style="text-align: right"><p>a</p><p>b</p></div>"#));
+  }
+
+  #[test]
+  fn test_advanced_table() {
+    let html = crate::parse(String::from("[[table class=\"wiki\" onclick=\"x()\"]]\n[[row]]\n[[hcell rowspan=\"2\" style=\"color: red; position: fixed\"]]\na\n[[/hcell]]\n[[/row]]\n[[/table]]"));
+    assert!(html.contains(r#"<table class="wiki"><tr><th style="color: red" rowspan="2"><p>a</p></th></tr></table>"#));
+  }
+
+  #[test]
+  fn test_render_fragment() {
+    let html = crate::parse_fragment(String::from("[[module CSS]]\np { color: red }\n[[/module]]\n**a**"), &Options::default());
+    assert_eq!(html, "<p><strong>a</strong></p>");
+  }
+
+  #[test]
+  fn test_link_with_disallowed_scheme() {
+    let html = crate::parse(String::from("[note:x some **label**] [a:b c] [ftp://example.com] [*ftp://example.com x] ftp://example.com"));
+    assert!(html.contains("<p>[note:x some **label**] [a:b c] [ftp://example.com] [*ftp://example.com x] ftp://example.com</p>"));
+  }
+
+  #[test]
+  fn test_block_markers_in_heading() {
+    let html = crate::parse(String::from("+ a||b ||~c"));
+    assert!(html.contains(r#"<h1 id="toc0">a||b ||~c</h1>"#));
+  }
+
+  #[test]
+  fn test_unclosed_quote_in_tag() {
+    let html = crate::parse(String::from("[[span style=\"color:red]]Warning[[/span]]\n\nSecond.\n\nA \"quote\".\n\n[[span]]x[[/span]]"));
+    assert!(html.contains(r#"<body><p><span style="color: red">Warning</span></p><p>Second.</p><p>A &quot;quote&quot;.</p><p><span>x</span></p></body>"#));
+  }
+
+  #[test]
+  fn test_line_break_before_line_start_markup() {
+    assert!(crate::parse(String::from("+ a _\n+ x")).contains(r#"<body><h1 id="toc0">a<br />+ x</h1></body>"#));
+    assert!(crate::parse(String::from("a _\n+ x\n: t : d")).contains("<body><p>a<br />+ x</p><dl><dt>t</dt><dd>d</dd></dl></body>"));
+  }
+}
@@ -67,13 +67,18 @@ impl Builder {
 
   pub fn close(&mut self) {
     self.data += "</";
-    self.data += &self.st.pop().unwrap_or(String::new());
+    self.data += &self.st.pop().unwrap_or_default();
     self.data += ">";
   }
 
   pub fn write(&mut self, text: &str) {
     self.data += &Self::escape_chars(text);
   }
+
+  /// Writes a trusted string without escaping. Used for the contents of raw text elements such as `style`.
+  pub fn write_raw(&mut self, text: &str) {
+    self.data += text;
+  }
 }
 
 impl From<Builder> for String {
@@ -0,0 +1,253 @@
+//! Pages of a site, read by `[[module ListPages]]` (`module::list_pages::ListPages`).
+
+use std::path::Path;
+
+/// A page of the site, without its source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page {
+  /// Unix name of the page, without the category, e.g. `scp-173`.
+  pub name: String,
+  /// `None` for pages in the `_default` category.
+  pub category: Option<String>,
+  pub title: String,
+  pub tags: Vec<String>,
+  /// Seconds since the Unix epoch, in UTC.
+  pub created_at: i64,
+  pub rating: i64,
+}
+
+impl Page {
+  /// `category:name`, or `name` in the `_default` category.
+  pub fn full_name(&self) -> String {
+    match &self.category {
+      Some(category) => format!("{}:{}", category, self.name),
+      None => self.name.clone(),
+    }
+  }
+
+  /// Category name, `_default` if the page has none.
+  pub fn category_name(&self) -> &str {
+    self.category.as_deref().unwrap_or("_default")
+  }
+}
+
+/// The pages of a site, e.g. from a database or a directory (`DirectoryRepository`).
+pub trait PageRepository: Send + Sync {
+  /// Every page of the site. The order does not matter, ListPages sorts them.
+  fn pages(&self) -> Vec<Page>;
+}
+
+impl std::fmt::Debug for dyn PageRepository {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str("PageRepository")
+  }
+}
+
+/// Pages stored as files, so that ListPages works offline.
+///
+/// `dir/name.txt` is the page `name` and `dir/category/name.txt` is `category:name`; any extension is accepted.
+/// A file may start with a header giving the metadata of the page:
+///
+/// ```text
+/// ---
+/// title: Some Title
+/// tags: tag1 tag2
+/// created_at: 2024-01-31 12:00:00
+/// rating: 10
+/// ---
+/// ```
+///
+/// The title defaults to the name, the creation date to the modification time of the file, and the rating to 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryRepository {
+  pages: Vec<Page>,
+}
+
+impl DirectoryRepository {
+  /// Reads the metadata of every page in `dir`. Later changes to the files are not seen.
+  /// Hidden files (`.git`, `.DS_Store` and so on) and files which are not UTF-8 are skipped.
+  pub fn load(dir: impl AsRef<Path>) -> std::io::Result<Self> {
+    let mut pages = vec![];
+    let mut dirs = vec![(dir.as_ref().to_path_buf(), None)];
+
+    // カテゴリは1階層だけ
+    while let Some((dir, category)) = dirs.pop() {
+      for entry in std::fs::read_dir(&dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+          continue;
+        }
+        let Some(name) = path.file_stem().and_then(|v| v.to_str()).map(crate::link::unix_name).filter(|v| !v.is_empty()) else {
+          continue;
+        };
+
+        if entry.file_type()?.is_dir() {
+          if category.is_none() {
+            dirs.push((path, Some(name).filter(|v| v != "_default")));
+          }
+          continue;
+        }
+
+        let Ok(source) = String::from_utf8(std::fs::read(&path)?) else {
+          continue;
+        };
+        let modified = entry.metadata()?.modified()?;
+        let modified = modified.duration_since(std::time::UNIX_EPOCH).map_or(0, |v| v.as_secs() as i64);
+        pages.push(read_page(name, category.clone(), &source, modified));
+      }
+    }
+
+    pages.sort_by_key(Page::full_name);
+    Ok(Self { pages })
+  }
+}
+
+impl PageRepository for DirectoryRepository {
+  fn pages(&self) -> Vec<Page> {
+    self.pages.clone()
+  }
+}
+
+/// Makes a page from the header of its source.
+fn read_page(name: String, category: Option<String>, source: &str, modified: i64) -> Page {
+  let mut page = Page { title: name.clone(), name, category, tags: vec![], created_at: modified, rating: 0 };
+
+  let source = crate::to_lf(source.to_string());
+  let Some(header) = source.strip_prefix("---\n").and_then(|v| v.split_once("\n---").map(|(header, _)| header)) else {
+    return page;
+  };
+
+  for line in header.lines() {
+    let Some((key, value)) = line.split_once(':') else {
+      continue;
+    };
+    let value = value.trim();
+    match key.trim() {
+      "title" => { page.title = value.to_string(); }
+      "tags" => { page.tags = value.split_whitespace().map(String::from).collect(); }
+      "created_at" => { page.created_at = parse_date(value).unwrap_or(page.created_at); }
+      "rating" => { page.rating = value.parse().unwrap_or(page.rating); }
+      _ => {}
+    }
+  }
+
+  page
+}
+
+/// Days from 1970-01-01 to the date. http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+  let year = if month <= 2 { year - 1 } else { year };
+  let era = year.div_euclid(400);
+  let year_of_era = year - era * 400;
+  let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  era * 146097 + day_of_era - 719468
+}
+
+/// Reverse of `days_from_civil`.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+  let days = days + 719468;
+  let era = days.div_euclid(146097);
+  let day_of_era = days - era * 146097;
+  let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let mp = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = year_of_era + era * 400 + i64::from(month <= 2);
+  (year, month, day)
+}
+
+/// Parses `YYYY-MM-DD`, optionally followed by ` HH:MM` or ` HH:MM:SS` (or `T` instead of the space), in UTC.
+pub fn parse_date(s: &str) -> Option<i64> {
+  let s = s.trim();
+  let (date, time) = match s.split_once([' ', 'T']) {
+    Some((date, time)) => (date, Some(time.trim())),
+    None => (s, None),
+  };
+
+  let mut date = date.splitn(3, '-').map(|v| v.parse::<i64>().ok());
+  let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+  // 2024-02-31 などは月をまたいでしまうので弾く
+  if !(1..=12).contains(&month) || !(1..=31).contains(&day) || civil_from_days(days_from_civil(year, month, day)) != (year, month, day) {
+    return None;
+  }
+
+  let mut seconds = 0;
+  if let Some(time) = time {
+    let parts: Vec<i64> = time.split(':').map(|v| v.parse::<i64>().ok()).collect::<Option<_>>()?;
+    let (hour, minute, second) = match parts[..] {
+      [hour, minute] => (hour, minute, 0),
+      [hour, minute, second] => (hour, minute, second),
+      _ => return None,
+    };
+    if !(0..24).contains(&hour) || !(0..60).contains(&minute) || !(0..60).contains(&second) {
+      return None;
+    }
+    seconds = hour * 3600 + minute * 60 + second;
+  }
+
+  Some(days_from_civil(year, month, day) * 86400 + seconds)
+}
+
+/// `YYYY-MM-DD HH:MM:SS` in UTC.
+pub fn format_date(timestamp: i64) -> String {
+  let (year, month, day) = civil_from_days(timestamp.div_euclid(86400));
+  let seconds = timestamp.rem_euclid(86400);
+  format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_dates() {
+    assert_eq!(parse_date("1970-01-01"), Some(0));
+    assert_eq!(parse_date("2024-02-29 12:34"), Some(1709210040));
+    assert_eq!(parse_date("2024-02-29T12:34:56"), Some(1709210096));
+    assert_eq!(parse_date("2024-13-01"), None);
+    assert_eq!(parse_date("2024-02-30"), None);
+    assert_eq!(parse_date("2023-02-29"), None);
+    assert_eq!(parse_date("2024-04-31 00:00"), None);
+    assert_eq!(parse_date("2024-12-31"), Some(1735603200));
+    assert_eq!(parse_date("yesterday"), None);
+    assert_eq!(format_date(1709210096), "2024-02-29 12:34:56");
+    assert_eq!(format_date(-1), "1969-12-31 23:59:59");
+  }
+
+  #[test]
+  fn test_read_page() {
+    let page = read_page(String::from("a"), None, "---\ntitle: Title: A\ntags: x y\ncreated_at: 2024-01-01\nrating: -3\n---\nbody", 5);
+    assert_eq!(page, Page {
+      name: String::from("a"),
+      category: None,
+      title: String::from("Title: A"),
+      tags: vec![String::from("x"), String::from("y")],
+      created_at: 1704067200,
+      rating: -3,
+    });
+
+    let page = read_page(String::from("b"), Some(String::from("c")), "title: not a header", 5);
+    assert_eq!((page.title.as_str(), page.created_at, page.full_name()), ("b", 5, String::from("c:b")));
+  }
+
+  #[test]
+  fn test_directory() {
+    let dir = std::env::temp_dir().join(format!("wikidot-parser-repository-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("News")).unwrap();
+    std::fs::write(dir.join("Start Page.txt"), "---\ntitle: Welcome\n---\n").unwrap();
+    std::fs::write(dir.join("News").join("first.txt"), "text").unwrap();
+    std::fs::write(dir.join(".DS_Store"), [0u8, 159, 146, 150]).unwrap();
+    std::fs::write(dir.join("image.png"), [137u8, 80, 78, 71, 255]).unwrap();
+    let repository = DirectoryRepository::load(&dir);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let pages = repository.unwrap().pages();
+    assert_eq!(pages.iter().map(|v| (v.full_name(), v.title.as_str())).collect::<Vec<_>>(), vec![
+      (String::from("news:first"), "first"),
+      (String::from("start-page"), "Welcome"),
+    ]);
+  }
+}
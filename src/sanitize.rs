@@ -0,0 +1,356 @@
+use std::collections::{HashMap, HashSet};
+
+/// Attribute key in `SanitizePolicy::allowed_attributes` which applies to every tag.
+pub const ANY_TAG: &str = "*";
+
+/// Allow-lists applied to user-written HTML elements (`[[span]]`, `[[div]]`, `[[a]]`...) and their styles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SanitizePolicy {
+  /// Tags which may be written to the output. Elements with other tags are replaced by their children.
+  pub allowed_tags: HashSet<String>,
+  /// Attributes allowed per tag. Attributes under `ANY_TAG` are allowed on every tag.
+  pub allowed_attributes: HashMap<String, HashSet<String>>,
+  /// CSS properties allowed in `style` attributes.
+  pub allowed_css_properties: HashSet<String>,
+  /// Whether `position` may take values other than `static` and `relative` (`absolute`, `fixed`, `sticky`, ...).
+  /// They take an element out of the flow and let a page draw over the surrounding site.
+  pub allow_any_position: bool,
+  /// Whether stylesheets of `[[module CSS]]` are written to the output. Their declarations are checked like `style` attributes.
+  pub allow_stylesheets: bool,
+}
+
+fn set(values: &[&str]) -> HashSet<String> {
+  values.iter().map(|v| v.to_string()).collect()
+}
+
+impl Default for SanitizePolicy {
+  fn default() -> Self {
+    Self {
+      allowed_tags: set(&["span", "div", "a"]),
+      allowed_attributes: HashMap::from([
+        (ANY_TAG.to_string(), set(&["style", "class"])),
+        ("a".to_string(), set(&["href"])),
+      ]),
+      allowed_css_properties: set(&[
+        "color", "background-color", "background",
+        "font", "font-family", "font-size", "font-style", "font-weight", "font-variant",
+        "text-align", "text-decoration", "text-indent", "text-transform", "text-shadow",
+        "line-height", "letter-spacing", "word-spacing", "white-space", "vertical-align",
+        "margin", "margin-top", "margin-right", "margin-bottom", "margin-left",
+        "padding", "padding-top", "padding-right", "padding-bottom", "padding-left",
+        "border", "border-top", "border-right", "border-bottom", "border-left",
+        "border-color", "border-style", "border-width", "border-radius", "border-collapse",
+        "width", "height", "min-width", "min-height", "max-width", "max-height",
+        "display", "float", "clear", "overflow", "position", "top", "right", "bottom", "left",
+        "opacity", "box-shadow", "list-style", "list-style-type",
+      ]),
+      allow_any_position: false,
+      allow_stylesheets: true,
+    }
+  }
+}
+
+/// Lowercased value without CSS comments, used to look for dangerous constructs.
+fn normalize_css(s: &str) -> String {
+  strip_css_comments(s).to_lowercase()
+}
+
+fn strip_css_comments(s: &str) -> String {
+  let mut res = String::new();
+  let mut rest = s;
+  while let Some(at) = rest.find("/*") {
+    res += &rest[..at];
+    rest = match rest[at+2..].find("*/") {
+      Some(end) => &rest[at+2+end+2..],
+      None => "",
+    };
+  }
+  res += rest;
+  res
+}
+
+/// Whether a selector of the list matches the document itself or every element (`html`, `body`, `:root`, a leading `*`).
+/// Such rules would restyle the page the content is embedded in.
+fn targets_document(prelude: &str) -> bool {
+  prelude.split(',').any(|selector| {
+    let mut compounds = selector.split(|c: char| c.is_whitespace() || "<>+~()".contains(c)).filter(|v| !v.is_empty()).peekable();
+    if compounds.peek().is_none_or(|v| v.starts_with('*')) {
+      return true;
+    }
+    compounds.any(|compound| {
+      let compound = compound.to_ascii_lowercase();
+      // `ns|body` の名前空間は無視する
+      let compound = compound.rsplit('|').next().unwrap_or_default();
+      let name = &compound[..compound.find(['.', '#', '[', ':']).unwrap_or(compound.len())];
+      matches!(name, "html" | "body") || compound.contains(":root")
+    })
+  })
+}
+
+/// Splits a declaration list by `;`, keeping semicolons inside quotes and parentheses.
+fn split_declarations(s: &str) -> Vec<&str> {
+  let mut res = vec![];
+  let mut depth: usize = 0;
+  let mut quote: Option<char> = None;
+  let mut begin = 0;
+
+  for (at, c) in s.char_indices() {
+    match (quote, c) {
+      (Some(q), _) if c == q => { quote = None; }
+      (Some(_), _) => {}
+      (None, '"' | '\'') => { quote = Some(c); }
+      (None, '(') => { depth += 1; }
+      (None, ')') => { depth = depth.saturating_sub(1); }
+      (None, ';') if depth == 0 => {
+        res.push(&s[begin..at]);
+        begin = at + 1;
+      }
+      _ => {}
+    }
+  }
+  res.push(&s[begin..]);
+
+  res
+}
+
+impl SanitizePolicy {
+  pub fn is_tag_allowed(&self, tag: &str) -> bool {
+    self.allowed_tags.contains(&tag.to_lowercase())
+  }
+
+  pub fn is_attribute_allowed(&self, tag: &str, attribute: &str) -> bool {
+    let attribute = attribute.to_lowercase();
+    [ANY_TAG.to_string(), tag.to_lowercase()].iter().any(|key| {
+      self.allowed_attributes.get(key).is_some_and(|v| v.contains(&attribute))
+    })
+  }
+
+  /// Checks a single CSS value for the given property.
+  pub fn is_css_value_allowed(&self, property: &str, value: &str) -> bool {
+    let value = normalize_css(value);
+
+    // escapes and comments can hide every keyword below
+    if value.contains('\\') || value.contains("/*") || value.contains(['<', '>', ';', '{', '}', '@']) {
+      return false;
+    }
+
+    for forbidden in ["expression(", "javascript:", "vbscript:", "behavior", "-moz-binding", "url(", "image-set(", "element("] {
+      if value.contains(forbidden) {
+        return false;
+      }
+    }
+
+    if property == "position" && !self.allow_any_position {
+      let value = value.trim().trim_end_matches("!important").trim();
+      if value != "static" && value != "relative" {
+        return false;
+      }
+    }
+
+    true
+  }
+
+  /// Parses a `style` attribute and keeps only the allowed declarations.
+  /// Returns `None` if nothing is left.
+  pub fn sanitize_style(&self, style: &str) -> Option<String> {
+    let mut declarations = vec![];
+
+    for declaration in split_declarations(style) {
+      let Some((property, value)) = declaration.split_once(':') else {
+        continue;
+      };
+
+      let property = normalize_css(property);
+      let property = property.trim();
+      let value = value.trim();
+
+      if value.is_empty() || !self.allowed_css_properties.contains(property) || !self.is_css_value_allowed(property, value) {
+        continue;
+      }
+
+      declarations.push(format!("{}: {}", property, value));
+    }
+
+    if declarations.is_empty() {
+      None
+    } else {
+      Some(declarations.join("; "))
+    }
+  }
+
+  /// Sanitizes the stylesheet of `[[module CSS]]`. Declarations go through `sanitize_style`,
+  /// and at-rules (`@import`, `@font-face`...), nested blocks and rules for the whole document
+  /// (`html`, `body`, `:root`, `*`) are dropped.
+  /// Returns `None` if nothing is left.
+  pub fn sanitize_stylesheet(&self, css: &str) -> Option<String> {
+    if !self.allow_stylesheets {
+      return None;
+    }
+
+    let css = strip_css_comments(css);
+    let mut rules = vec![];
+    let mut rest = css.as_str();
+
+    while let Some(at) = rest.find(['{', ';']) {
+      let prelude = rest[..at].trim();
+      if rest.as_bytes()[at] == b';' { // `@import ...;` など
+        rest = &rest[at+1..];
+        continue;
+      }
+
+      // 対応する `}` を探す
+      let mut depth: usize = 0;
+      let Some(end) = rest[at..].find(|c| {
+        match c {
+          '{' => { depth += 1; }
+          '}' => { depth -= 1; }
+          _ => {}
+        }
+        depth == 0
+      }).map(|v| at + v) else {
+        break;
+      };
+      let body = &rest[at+1..end];
+      rest = &rest[end+1..];
+
+      // `<` は `</style>` で抜け出すのに使える
+      if prelude.is_empty() || prelude.starts_with('@') || prelude.contains(['<', '\\']) || body.contains('{') || targets_document(prelude) {
+        continue;
+      }
+      if let Some(declarations) = self.sanitize_style(body) {
+        rules.push(format!("{} {{ {} }}", prelude, declarations));
+      }
+    }
+
+    if rules.is_empty() {
+      None
+    } else {
+      Some(rules.join("\n"))
+    }
+  }
+
+  /// Drops the attributes which are not allowed on the tag, and sanitizes the remaining `style`.
+  pub fn sanitize_attributes<K: AsRef<str>, V: AsRef<str>>(&self, tag: &str, attributes: &[(K, V)]) -> Vec<(String, String)> {
+    let mut res = vec![];
+
+    for (key, value) in attributes {
+      let (key, value) = (key.as_ref().to_lowercase(), value.as_ref());
+      if !self.is_attribute_allowed(tag, &key) {
+        continue;
+      }
+
+      if key == "style" {
+        if let Some(style) = self.sanitize_style(value) {
+          res.push((key, style));
+        }
+      } else {
+        res.push((key, value.to_string()));
+      }
+    }
+
+    res
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sf(st: &str) -> String {
+    String::from(st)
+  }
+
+  #[test]
+  fn test_style_passthrough() {
+    let policy = SanitizePolicy::default();
+    assert_eq!(policy.sanitize_style("color: red; font-weight: bold"), Some(sf("color: red; font-weight: bold")));
+  }
+
+  #[test]
+  fn test_style_unknown_property() {
+    let policy = SanitizePolicy::default();
+    assert_eq!(policy.sanitize_style("color: red; behavior: url(x.htc); -moz-binding: url(x)"), Some(sf("color: red")));
+  }
+
+  #[test]
+  fn test_style_dangerous_values() {
+    let policy = SanitizePolicy::default();
+    assert_eq!(policy.sanitize_style("background: url(javascript:alert(1))"), None);
+    assert_eq!(policy.sanitize_style("width: expression(alert(1))"), None);
+    assert_eq!(policy.sanitize_style("width: expr/**/ession(alert(1))"), None);
+    assert_eq!(policy.sanitize_style(r"width: expr\65ssion(alert(1))"), None);
+  }
+
+  #[test]
+  fn test_style_position() {
+    let mut policy = SanitizePolicy::default();
+    assert_eq!(policy.sanitize_style("position: fixed; top: 0; left: 0"), Some(sf("top: 0; left: 0")));
+    assert_eq!(policy.sanitize_style("position: FIXED !important"), None);
+    assert_eq!(policy.sanitize_style("position:absolute;top:0;left:0;width:100%;height:100%"), Some(sf("top: 0; left: 0; width: 100%; height: 100%")));
+    assert_eq!(policy.sanitize_style("position: sticky"), None);
+    assert_eq!(policy.sanitize_style("position: inherit"), None);
+    assert_eq!(policy.sanitize_style("position: relative"), Some(sf("position: relative")));
+    assert_eq!(policy.sanitize_style("position: static !important"), Some(sf("position: static !important")));
+
+    policy.allow_any_position = true;
+    assert_eq!(policy.sanitize_style("position: fixed"), Some(sf("position: fixed")));
+    assert_eq!(policy.sanitize_style("position: absolute"), Some(sf("position: absolute")));
+  }
+
+  #[test]
+  fn test_style_semicolon_in_quotes() {
+    let policy = SanitizePolicy::default();
+    // the quoted semicolon does not split the declaration, but the value is still rejected
+    assert_eq!(policy.sanitize_style(r#"font-family: "a;b", serif; color: red"#), Some(sf("color: red")));
+    assert_eq!(policy.sanitize_style(r#"font-family: "Noto Sans", serif"#), Some(sf(r#"font-family: "Noto Sans", serif"#)));
+  }
+
+  #[test]
+  fn test_attributes() {
+    let policy = SanitizePolicy::default();
+    let attributes = vec![
+      (sf("style"), sf("color: red; position: fixed")),
+      (sf("class"), sf("box")),
+      (sf("onclick"), sf("alert(1)")),
+      (sf("href"), sf("/page")),
+    ];
+    assert_eq!(policy.sanitize_attributes("span", &attributes), vec![
+      (sf("style"), sf("color: red")),
+      (sf("class"), sf("box")),
+    ]);
+    assert_eq!(policy.sanitize_attributes("a", &attributes), vec![
+      (sf("style"), sf("color: red")),
+      (sf("class"), sf("box")),
+      (sf("href"), sf("/page")),
+    ]);
+  }
+
+  #[test]
+  fn test_stylesheet() {
+    let mut policy = SanitizePolicy::default();
+    let css = "@import url(x.css);\n.a > b { color: red; behavior: url(x) }\n@media print { .a { color: blue } }\n/* } */ p{position:fixed} .b{font-weight:bold;}";
+    assert_eq!(policy.sanitize_stylesheet(css), Some(sf(".a > b { color: red }\n.b { font-weight: bold }")));
+    assert_eq!(policy.sanitize_stylesheet("a</style><script>{color: red}"), None);
+    assert_eq!(policy.sanitize_stylesheet(".a { color: red"), None);
+
+    // 埋め込み先のページ全体に効くルール
+    assert_eq!(policy.sanitize_stylesheet("body, * { display: none }"), None);
+    assert_eq!(policy.sanitize_stylesheet("html .a { color: red }"), None);
+    assert_eq!(policy.sanitize_stylesheet("BODY.dark p { color: red }"), None);
+    assert_eq!(policy.sanitize_stylesheet("* { color: red }"), None);
+    assert_eq!(policy.sanitize_stylesheet("*.a { color: red }"), None);
+    assert_eq!(policy.sanitize_stylesheet(":root { color: red }"), None);
+    assert_eq!(policy.sanitize_stylesheet(".a, :is(body) { color: red }"), None);
+    assert_eq!(policy.sanitize_stylesheet(".a * { color: red } .body > .html:hover { color: blue }"), Some(sf(".a * { color: red }\n.body > .html:hover { color: blue }")));
+
+    policy.allow_stylesheets = false;
+    assert_eq!(policy.sanitize_stylesheet(".a { color: red }"), None);
+  }
+
+  #[test]
+  fn test_tags() {
+    let policy = SanitizePolicy::default();
+    assert!(policy.is_tag_allowed("SPAN"));
+    assert!(!policy.is_tag_allowed("script"));
+  }
+}
@@ -1,6 +1,14 @@
+mod attributes;
+mod brackets;
+
+use std::borrow::Cow;
+
+pub use crate::ast::table_cell::Style as CellStyle;
+
 // According to https://scp-wiki.wikidot.com/wiki-syntax
+// Text and names borrow from the source unless they contain escapes.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Token {
+pub enum Token<'a> {
   Bold, // **
   Italics, // // (double-slash)
   Underline, // __
@@ -9,22 +17,29 @@ pub enum Token {
   MonospacedClose, // }}
   Superscript, // ^^
   Subscript, // ,,
-  ElementBegin{name: String, attributes: Vec<(String, String)>}, // [[span style="color:red"]]
-  ElementEnd(String), // [[/span]]
-  ColoredBeginColorCode(String), // ##color|
-  ColoredBeginColorName(String), // TODO: Enum化も検討
+  ElementBegin{name: Cow<'a, str>, attributes: Vec<(Cow<'a, str>, Cow<'a, str>)>, arguments: Vec<(Cow<'a, str>, Cow<'a, str>)>}, // [[span style="color:red"]], [[include page | key=value]]
+  ElementEnd(Cow<'a, str>), // [[/span]]
+  ColoredBeginColorCode(Cow<'a, str>), // ##color|
+  ColoredBeginColorName(Cow<'a, str>), // TODO: Enum化も検討
   ColoredEnd, // ##
-  NamedLink{link: String, name: String},
-  PageLink{link: String, name: String},
+  NamedLink{link: Cow<'a, str>, name: Cow<'a, str>, new_tab: bool, source: Cow<'a, str>}, // [*url name] で新しいタブ. sourceは書かれたまま (URLが使えないときに表示する)
+  PageLink{link: Cow<'a, str>, target: crate::link::PageLink, name: Cow<'a, str>, new_tab: bool}, // linkは書かれたまま. [[[*page]]] で新しいタブ
   BlockQuote(std::num::NonZeroUsize), // TODO: u8で管理していた頃の名残のコードを吹き飛ばす
-  CellSeparator(Option<crate::ast::table_cell::Style>), // ||[~<=>]?
+  Heading(std::num::NonZeroUsize), // +{1,6} at the beginning of a line
+  CellSeparator(Option<CellStyle>), // ||[~<=>]?
   NewLine, // \n
+  LineBreak, // " _" at the end of a line
+  DefinitionTerm, // ": " at the beginning of a line
+  DefinitionSeparator, // " : " after DefinitionTerm
+  HorizontalRule, // ---- (4つ以上) だけの行
+  Stylesheet(Cow<'a, str>), // [[module CSS]] ... [[/module]] の中身. 解析せずにそのまま
+  Module{name: Cow<'a, str>, args: Vec<(Cow<'a, str>, Cow<'a, str>)>, body: Option<Cow<'a, str>>, position: usize}, // [[module Name args]] body [[/module]]. positionは [[ の位置
 
-  Text(String)
+  Text(Cow<'a, str>)
 }
 
 use crate::ast::ParseFrame;
-impl TryFrom<Token> for crate::ast::ParseFrame {
+impl TryFrom<Token<'_>> for crate::ast::ParseFrame {
   type Error = ();
 
   fn try_from(value: Token) -> Result<Self, Self::Error> {
@@ -37,58 +52,112 @@ impl TryFrom<Token> for crate::ast::ParseFrame {
         Token::MonospacedClose => Err(()),
         Token::Superscript => Ok(ParseFrame::Superscript),
         Token::Subscript => Ok(ParseFrame::Subscript),
-        Token::ElementBegin { name: _, attributes: _ } => Err(()),
+        Token::ElementBegin { .. } => Err(()),
         Token::ElementEnd(_) => Err(()),
         Token::ColoredBeginColorName(_) => Err(()),
         Token::ColoredBeginColorCode(_) => Err(()),
         Token::ColoredEnd => Err(()),
-        Token::NamedLink { link: _, name: _ } => Err(()),
-        Token::PageLink { link: _, name: _ } => Err(()),
+        Token::NamedLink { .. } => Err(()),
+        Token::PageLink { .. } => Err(()),
         Token::BlockQuote(_) => Err(()),
+        Token::Heading(_) => Err(()),
         Token::CellSeparator(_) => Err(()),
         Token::NewLine => Err(()),
+        Token::LineBreak => Err(()),
+        Token::DefinitionTerm => Err(()),
+        Token::DefinitionSeparator => Err(()),
+        Token::HorizontalRule => Err(()),
+        Token::Stylesheet(_) => Err(()),
+        Token::Module { .. } => Err(()),
         Token::Text(_) => Err(()),
       }
   }
 }
 
 
-struct TokenData {
-  res: Vec<Token>,
-  buf: String,
+struct TokenData<'a> {
+  src: &'a str,
+  res: std::collections::VecDeque<Token<'a>>, // まだ返していないtoken
+  buf: Option<Cow<'a, str>>,
+  buf_end: usize, // bufが借用しているsrc上の終端
+  diagnostics: Vec<crate::diagnostic::Diagnostic>,
+  anchors: Vec<(String, usize)>, // [[# name]] の名前と位置. 最後に重複を調べる
+  heading_count: usize,
 }
 
-impl TokenData {
-  fn new() -> Self {
+impl<'a> TokenData<'a> {
+  fn new(src: &'a str) -> Self {
     Self {
-      res: vec![],
-      buf: String::new(),
+      src,
+      res: std::collections::VecDeque::new(),
+      buf: None,
+      buf_end: 0,
+      diagnostics: vec![],
+      anchors: vec![],
+      heading_count: 0,
     }
   }
 
-  fn add_char(&mut self, c: char) {
-    self.buf.push(c);
+  /// Reports anchors whose names are used twice, or by a heading id (`toc0`, `toc1`, ...).
+  fn check_anchors(&mut self) {
+    let mut seen = std::collections::HashSet::new();
+    for (name, position) in std::mem::take(&mut self.anchors) {
+      let is_heading_id = name.strip_prefix(crate::constants::HEADING_ID_PREFIX)
+        .and_then(|v| v.parse::<usize>().ok())
+        .is_some_and(|v| v < self.heading_count);
+      if is_heading_id || seen.contains(&name) {
+        self.diagnostics.push(crate::diagnostic::Diagnostic::DuplicateAnchor { name, position });
+      } else {
+        seen.insert(name);
+      }
+    }
   }
 
-  fn flush(&mut self) {
-    if !self.buf.is_empty() {
-      let text = std::mem::take(&mut self.buf);
-      self.res.push(Token::Text(text));
+  /// Reports the problems in the link label at `src[begin..end]`. The label itself is tokenized again when it is parsed.
+  fn scan_label(&mut self, begin: usize, end: usize) {
+    let mut label = Tokenizer::new_inline(&self.src[begin..end]);
+    label.by_ref().for_each(drop);
+    for mut diagnostic in label.data.diagnostics {
+      *diagnostic.position_mut() += begin;
+      self.diagnostics.push(diagnostic);
     }
+    self.anchors.extend(label.data.anchors.into_iter().map(|(name, position)| (name, position + begin)));
   }
 
-  fn flush_and_add_token(&mut self, t: Token) {
-    self.flush();
-    self.res.push(t);
+  /// Adds `src[begin..end]` to the text. Contiguous slices are kept borrowed.
+  fn add_slice(&mut self, begin: usize, end: usize) {
+    match &mut self.buf {
+      None => {
+        self.buf = Some(Cow::Borrowed(&self.src[begin..end]));
+      }
+      Some(Cow::Borrowed(v)) if self.buf_end == begin => {
+        self.buf = Some(Cow::Borrowed(&self.src[begin - v.len()..end]));
+      }
+      Some(v) => {
+        v.to_mut().push_str(&self.src[begin..end]);
+      }
+    }
+    self.buf_end = end;
+  }
+
+  /// Adds text which is not in the source.
+  fn add_str(&mut self, s: &str) {
+    self.buf.get_or_insert_default().to_mut().push_str(s);
+  }
+
+  fn flush(&mut self) {
+    if let Some(text) = self.buf.take() && !text.is_empty() {
+      self.res.push_back(Token::Text(text));
+    }
   }
 
-  fn get_value(mut self) -> Vec<Token> {
+  fn flush_and_add_token(&mut self, t: Token<'a>) {
     self.flush();
-    self.res
+    self.res.push_back(t);
   }
 }
 
-fn is_next_eq(at: usize, v: &[char], c: char) -> bool {
+fn is_next_eq(at: usize, v: &[u8], c: u8) -> bool {
   if at+1 >= v.len() {
     false
   } else {
@@ -96,174 +165,319 @@ fn is_next_eq(at: usize, v: &[char], c: char) -> bool {
   }
 }
 
+/// Byte length of the character at `at`.
+fn char_len(s: &str, at: usize) -> usize {
+  s[at..].chars().next().map_or(1, char::len_utf8)
+}
+
 /// requires LF-formatted string
-fn get_unescaped_string(s: &[char]) -> String {
+fn get_unescaped_string(s: &str) -> Cow<'_, str> {
+  if !s.contains('\\') {
+    return Cow::Borrowed(s);
+  }
+
   let mut target_str: String = String::new(); // エスケープを取り除かれた文字列
   let mut ignore_next = false;
-  for char in s {
+  for char in s.chars() {
     if ignore_next {
-      target_str.push(*char);
+      target_str.push(char);
       ignore_next = false;
-    } else if *char == '\\' {
+    } else if char == '\\' {
       ignore_next = true;
     } else {
-      target_str.push(*char);
+      target_str.push(char);
     }
   }
-  target_str
+  Cow::Owned(target_str)
 }
 
-pub fn tokenize(s: String) -> Vec<Token> {
-  let mut data: TokenData = TokenData::new();
+/// `str::split_once` which keeps borrowed strings borrowed.
+fn split_once_cow(s: Cow<'_, str>, delimiter: char) -> Option<(Cow<'_, str>, Cow<'_, str>)> {
+  match s {
+    Cow::Borrowed(v) => v.split_once(delimiter).map(|(a, b)| (Cow::Borrowed(a), Cow::Borrowed(b))),
+    Cow::Owned(v) => v.split_once(delimiter).map(|(a, b)| (Cow::Owned(a.to_string()), Cow::Owned(b.to_string()))),
+  }
+}
 
-  let chars: Vec<char> = s.chars().collect();
+#[cfg(test)]
+pub fn tokenize(s: &str) -> Vec<Token<'_>> {
+  Tokenizer::new(s).collect()
+}
 
-  let mut is_escaping_parse = false;
+/// Letters, digits and non-ASCII characters (e.g. Japanese) are part of a word.
+fn is_word_byte(b: u8) -> bool {
+  b.is_ascii_alphanumeric() || !b.is_ascii()
+}
 
-  let tokenize_if_double: Vec<(char, Token)> = vec![
-    ('*', Token::Bold),
-    ('/', Token::Italics),
-    ('_', Token::Underline),
-    ('-', Token::Strikethrough),
-    ('{', Token::MonospacedOpen),
-    ('}', Token::MonospacedClose),
-    ('^', Token::Superscript),
-    (',', Token::Subscript),
-  ];
+const TOKENIZE_IF_DOUBLE: [(u8, Token<'static>); 8] = [
+  (b'*', Token::Bold),
+  (b'/', Token::Italics),
+  (b'_', Token::Underline),
+  (b'-', Token::Strikethrough),
+  (b'{', Token::MonospacedOpen),
+  (b'}', Token::MonospacedClose),
+  (b'^', Token::Superscript),
+  (b',', Token::Subscript),
+];
 
-  let mut i = 0;
-  'chars_loop: while i < chars.len() {
-    let mut done = false;
-    
-    // check escape
-    if chars[i] == '@' && is_next_eq(i, &chars, '@') {
-      i += 2;
-      is_escaping_parse = !is_escaping_parse;
-      continue 'chars_loop;
+/// Produces tokens on demand, so the whole token list is never held in memory.
+/// Only the positions of brackets, quotes and newlines are collected up front.
+pub struct Tokenizer<'a> {
+  data: TokenData<'a>,
+  brackets: brackets::BracketIndex,
+  i: usize, // バイト位置. 記法はすべてASCIIなので, 文字の途中を指すことはない
+  is_escaping_parse: bool,
+  inline_only: bool,
+  in_definition_term: bool, // ": term : definition" の term を読んでいる
+  continued_line: Option<usize>, // " _" で前の行から続いている行の先頭
+}
+
+impl<'a> Tokenizer<'a> {
+  /// requires LF-formatted string
+  pub fn new(s: &'a str) -> Self {
+    Self {
+      data: TokenData::new(s),
+      brackets: brackets::BracketIndex::new(s),
+      i: 0,
+      is_escaping_parse: false,
+      inline_only: false,
+      in_definition_term: false,
+      continued_line: None,
     }
+  }
+
+  /// Tokenizes text inside a line, e.g. a link label. Headings, quotes and table cells are read as text.
+  pub fn new_inline(s: &'a str) -> Self {
+    Self { inline_only: true, ..Self::new(s) }
+  }
+
+  pub fn peek(&mut self) -> Option<&Token<'a>> {
+    self.peek_nth(0)
+  }
+
+  /// Looks `n` tokens ahead without consuming them.
+  pub fn peek_nth(&mut self, n: usize) -> Option<&Token<'a>> {
+    while self.data.res.len() <= n && self.step() {}
+    self.data.res.get(n)
+  }
 
-    if is_escaping_parse {
-      data.add_char(chars[i]);
-      i += 1;
-      continue 'chars_loop;
+  /// Problems found in the part of the source tokenized so far.
+  pub fn diagnostics(&self) -> &[crate::diagnostic::Diagnostic] {
+    &self.data.diagnostics
+  }
+
+  pub fn into_diagnostics(self) -> Vec<crate::diagnostic::Diagnostic> {
+    self.data.diagnostics
+  }
+
+  /// Tokenizes the next piece of the source. Returns false at the end.
+  fn step(&mut self) -> bool {
+    let s = self.data.src;
+    let bytes = s.as_bytes();
+    let data = &mut self.data;
+    let brackets = &mut self.brackets;
+    let inline_only = self.inline_only;
+
+    let mut i = self.i;
+    if i >= bytes.len() {
+      data.flush();
+      // ラベルのアンカーは読み込んだ側がページ全体で調べる
+      if !inline_only {
+        data.check_anchors();
+      }
+      return false;
     }
+    // 見出しや引用などは行頭でだけ読む. " _" で続く行は行頭として扱わない
+    let line_start = !inline_only && (i == 0 || bytes[i-1] == b'\n') && self.continued_line != Some(i);
 
-    for (c, t) in &tokenize_if_double {
-      if chars[i] == *c && is_next_eq(i, &chars, *c) {
-        data.flush_and_add_token(t.clone());
+    'step: {
+      let mut done = false;
+    
+      // check escape
+      if bytes[i] == b'@' && is_next_eq(i, bytes, b'@') {
         i += 2;
-        continue 'chars_loop;
+        self.is_escaping_parse = !self.is_escaping_parse;
+        break 'step;
       }
-    }
 
-    match chars[i] {
-      // TODO: is_in_double_quotationを実装
-      '[' => 'square_brace: {
-        if is_next_eq(i, &chars, '[') {
-          if is_next_eq(i+1, &chars, '[') {
-            let mut elem_specifier_len = 0;
-            while i+3+elem_specifier_len < chars.len() { // i+3+elem_specifier_lenに本体を伸ばせるかを見る
-              if chars[i+2+elem_specifier_len] != '\\' && chars[i+3+elem_specifier_len] == ']' && is_next_eq(i+3+elem_specifier_len, &chars, ']') && is_next_eq(i+3+elem_specifier_len+1, &chars, ']') {
-                break;
-              }
-              if chars[i+3+elem_specifier_len] == '\n' {
-                break 'square_brace;
-              }
-              elem_specifier_len+=1;
-            };
+      if self.is_escaping_parse {
+        let len = char_len(s, i);
+        data.add_slice(i, i+len);
+        i += len;
+        break 'step;
+      }
 
-            let target_str: String = get_unescaped_string(&chars[i+3..i+3+elem_specifier_len]);
+      // 地の文のURLはリンクにする (`http://` で斜体にしない)
+      if bytes[i].is_ascii_alphabetic() && (i == 0 || !is_word_byte(bytes[i-1])) && let Some(len) = crate::url::bare_url_len(&s[i..]) {
+        let url = Cow::Borrowed(&s[i..i+len]);
+        data.flush_and_add_token(Token::NamedLink { link: url.clone(), name: url.clone(), new_tab: false, source: url });
+        i += len;
+        break 'step;
+      }
 
-            if target_str.contains('|') {
-              let v: (&str, &str) = target_str.split_once('|').unwrap();
+      // 水平線
+      if bytes[i] == b'-' && line_start {
+        let len = bytes[i..].iter().take_while(|b| **b == b'-').count();
+        if len >= 4 && bytes.get(i+len).is_none_or(|b| *b == b'\n') {
+          data.flush_and_add_token(Token::HorizontalRule);
+          i += len;
+          break 'step;
+        }
+      }
 
-              data.flush_and_add_token(Token::PageLink { link: String::from(v.0), name: String::from(v.1) });
-            } else {
-              data.flush_and_add_token(Token::PageLink { link: target_str, name: String::from("") });
-            }
+      // 水平線にならない3つ以上の `-` は文字のまま (`-----x` を打ち消し線にしない)
+      if bytes[i] == b'-' {
+        let len = bytes[i..].iter().take_while(|b| **b == b'-').count();
+        if len >= 3 {
+          data.add_slice(i, i+len);
+          i += len;
+          break 'step;
+        }
+      }
 
-            i += 3 + elem_specifier_len + 3;
-            done = true;
-          } else {
-            // elem_begin
-            let mut elem_specifier_len = 0;
-            while i+2+elem_specifier_len < chars.len() { // i+2+elem_specifier_lenに本体を伸ばせるかを見る
-              if chars[i+1+elem_specifier_len] != '\\' && chars[i+2+elem_specifier_len] == ']' && is_next_eq(i+2+elem_specifier_len, &chars, ']') {
-                break;
-              }
-              // \nは許す
-              elem_specifier_len+=1;
-            };
+      for (c, t) in &TOKENIZE_IF_DOUBLE {
+        // 単語中の `--` (foo--bar) は打ち消し線にしない
+        if *c == b'-' && i > 0 && is_word_byte(bytes[i-1]) && bytes.get(i+2).is_some_and(|b| is_word_byte(*b)) {
+          continue;
+        }
+        if bytes[i] == *c && is_next_eq(i, bytes, *c) {
+          data.flush_and_add_token(t.clone());
+          i += 2;
+          break 'step;
+        }
+      }
 
-            let target_str: String = get_unescaped_string(&chars[i+2..i+2+elem_specifier_len]);
+      match bytes[i] {
+        b'[' => 'square_brace: {
+          if is_next_eq(i, bytes, b'[') {
+            if is_next_eq(i+1, bytes, b'[') {
+              let Some(end) = brackets.page_link_end(i) else {
+                break 'square_brace; // 閉じられていなければ文字として扱う
+              };
 
-            if target_str.starts_with("/") { // 閉じタグ
-              data.flush_and_add_token(Token::ElementEnd(target_str.strip_prefix("/").unwrap().into()));
+              let new_tab = bytes[i+3] == b'*';
+              let target_str = get_unescaped_string(&s[i+3+usize::from(new_tab)..end]);
+
+              let (link, name) = if target_str.contains('|') {
+                split_once_cow(target_str, '|').unwrap()
+              } else {
+                (target_str, Cow::Borrowed(""))
+              };
+              let Some(target) = crate::link::PageLink::parse(&link) else {
+                break 'square_brace;
+              };
+              if !name.is_empty() && let Some(bar) = s[i+3..end].find('|') {
+                data.scan_label(i + 3 + bar + 1, end);
+              }
+              data.flush_and_add_token(Token::PageLink { link, target, name, new_tab });
+
+              i = end + 3;
+              done = true;
             } else {
-              let mut name = String::new();
-              let mut attributes: Vec<(String, String)> = vec![];
+              // elem_begin (\nは許す)
+              let Some(end) = brackets.specifier_end(s, i) else {
+                break 'square_brace;
+              };
+              let specifier = &s[i+2..end];
 
-              for (at, v) in target_str.replace("|", " ").split_whitespace().enumerate() {
-                if at == 0 {
-                  name = String::from(v);
+              if let Some(name) = specifier.strip_prefix('/') { // 閉じタグ
+                data.flush_and_add_token(Token::ElementEnd(get_unescaped_string(name)));
+              } else {
+                let attributes::ElementSpecifier { name, attributes, arguments, duplicates } = attributes::parse_element_specifier(specifier);
+
+                for duplicate in duplicates {
+                  data.diagnostics.push(crate::diagnostic::Diagnostic::DuplicateAttribute { element: name.to_string(), name: duplicate, position: i });
                 }
-                else if v.contains('=') {
-                  let v: Vec<&str> = v.splitn(2, '=').collect();
-                  if v.len() == 2 && v[1].len() >= 2 {
-                    attributes.push((String::from(v[0]), String::from(&v[1][1..(v[1].len()-1)])));
-                  }
-                } else {
-                  attributes.push((String::from(""), String::from(v)));
+                if name == "#" && let Some((_, anchor)) = attributes.iter().find(|(key, _)| key.is_empty()) {
+                  data.anchors.push((anchor.trim().to_string(), i));
                 }
-              }
+                if name.trim().eq_ignore_ascii_case("include") {
+                  let page = attributes.iter().find(|(key, _)| key.is_empty()).map(|(_, page)| page.trim().to_string()).unwrap_or_default();
+                  data.diagnostics.push(crate::diagnostic::Diagnostic::UnsupportedInclude { page, position: i });
+                }
+                let module_name = name.eq_ignore_ascii_case("module")
+                  .then(|| attributes.iter().position(|(key, _)| key.is_empty()))
+                  .flatten();
+                let Some(name_at) = module_name else {
+                  data.flush_and_add_token(Token::ElementBegin { name, attributes, arguments });
+                  i = end + 2;
+                  done = true;
+                  break 'square_brace;
+                };
 
-              data.flush_and_add_token(Token::ElementBegin { name, attributes });
-            }
-            i += 2 + elem_specifier_len + 2;
-            done = true;
-          }
-        } else {
-          let mut elem_specifier_len = 0;
+                // 本文は [[/module]] まで. 間に別の [[module]] があれば本文はない
+                let module_end = brackets.module_end(s, end + 2).filter(|module_end| !s[end+2..*module_end].contains("[[module"));
+                // CSSはwikitextとして読まない
+                if attributes[name_at].1.trim().eq_ignore_ascii_case("css") {
+                  match module_end {
+                    Some(module_end) => {
+                      data.flush_and_add_token(Token::Stylesheet(Cow::Borrowed(&s[end+2..module_end])));
+                      i = module_end + brackets::MODULE_END.len();
+                    }
+                    None => {
+                      data.flush_and_add_token(Token::ElementBegin { name, attributes, arguments });
+                      i = end + 2;
+                    }
+                  }
+                  done = true;
+                  break 'square_brace;
+                }
 
-          while i+1+elem_specifier_len < chars.len() { // i+1+elem_specifier_lenに本体を伸ばせるかを見る
-            if chars[i+elem_specifier_len] != '\\' && chars[i+1+elem_specifier_len] == ']' {
-              break;
+                let mut args = attributes;
+                let (_, module_name) = args.remove(name_at);
+                args.extend(arguments);
+                let body = module_end.map(|module_end| Cow::Borrowed(&s[end+2..module_end]));
+                data.flush_and_add_token(Token::Module { name: Cow::Owned(module_name.trim().to_string()), args, body, position: i });
+                i = module_end.map_or(end + 2, |module_end| module_end + brackets::MODULE_END.len());
+                done = true;
+                break 'square_brace;
+              }
+              i = end + 2;
+              done = true;
             }
-            if chars[i+1+elem_specifier_len] == '\n' {
+          } else {
+            let Some(end) = brackets.named_link_end(i) else {
               break 'square_brace;
-            }
-            elem_specifier_len+=1;
-          };
-
-          let target_str = get_unescaped_string(&chars[i+1..i+1+elem_specifier_len]);
+            };
 
-          if let Some(v) = target_str.split_once(" ") {
-            let url_regex = regex::Regex::new(r"^https?:\/\/(www\.)?[-a-zA-Z0-9@:%._\+~#=]{1,256}\.[a-zA-Z0-9()]{1,6}\b([-a-zA-Z0-9()@:%_\+.~#?&//=]*)$").unwrap();
+            let new_tab = bytes[i+1] == b'*';
+            let target_str = get_unescaped_string(&s[i+1+usize::from(new_tab)..end]);
+            let source = Cow::Borrowed(&s[i..=end]);
+            let label_begin = s[i..end].find(' ').map_or(end, |v| i + v + 1);
 
-            if url_regex.is_match(v.0) {
-              data.flush_and_add_token(Token::NamedLink {
-                link: String::from(v.0),
-                name: String::from(v.1),
-              });
-              i += 1 + elem_specifier_len + 1;
+            if target_str.starts_with('#') {
+              // ページ内リンク
+              if let Some((link, name)) = split_once_cow(target_str, ' ') {
+                data.scan_label(label_begin, end);
+                data.flush_and_add_token(Token::NamedLink { link, name, new_tab, source });
+                i = end + 1;
+                done = true;
+              }
+            } else if !target_str.contains(' ') && crate::url::is_absolute_url(&target_str) {
+              // [url] はURLをそのまま表示する
+              data.flush_and_add_token(Token::NamedLink { link: target_str.clone(), name: target_str, new_tab, source });
+              i = end + 1;
+              done = true;
+            } else if let Some((link, name)) = split_once_cow(target_str, ' ') && crate::url::is_absolute_url(&link) {
+              data.scan_label(label_begin, end);
+              data.flush_and_add_token(Token::NamedLink { link, name, new_tab, source });
+              i = end + 1;
               done = true;
             }
           }
         }
-      }
 
-      '|' => {
-        if is_next_eq(i, &chars, '|') {
-          if is_next_eq(i+1, &chars, '~') {
+        b'|' if !inline_only && is_next_eq(i, bytes, b'|') => {
+          if is_next_eq(i+1, bytes, b'~') {
             data.flush_and_add_token(Token::CellSeparator(Some(crate::ast::table_cell::Style::Title)));
             i += 3;
-          } else if is_next_eq(i+1, &chars, '<') {
+          } else if is_next_eq(i+1, bytes, b'<') {
             data.flush_and_add_token(Token::CellSeparator(Some(crate::ast::table_cell::Style::LeftAligned)));
             i += 3;
-          } else if is_next_eq(i+1, &chars, '>') {
+          } else if is_next_eq(i+1, bytes, b'>') {
             data.flush_and_add_token(Token::CellSeparator(Some(crate::ast::table_cell::Style::RightAligned)));
             i += 3;
-          } else if is_next_eq(i+1, &chars, '=') {
+          } else if is_next_eq(i+1, bytes, b'=') {
             data.flush_and_add_token(Token::CellSeparator(Some(crate::ast::table_cell::Style::CenterAligned)));
             i += 3;
           } else {
@@ -272,56 +486,55 @@ pub fn tokenize(s: String) -> Vec<Token> {
           }
           done = true;
         }
-      }
 
-      '\\' => {
-        if i+1 >= chars.len() || chars[i+1] == '\n' {
-          data.add_char('\n');
-          i += 2;
-        } else {
-          data.add_char(chars[i+1]);
-          i += 2;
+        b'\\' => {
+          if i+1 >= bytes.len() || bytes[i+1] == b'\n' {
+            data.add_str("\n");
+            i = (i + 2).min(bytes.len());
+          } else {
+            let len = char_len(s, i+1);
+            data.add_slice(i+1, i+1+len);
+            i += 1 + len;
+          }
+          done = true;
         }
-        done = true;
-      }
 
-      '>' => {
-        if i == 0 || chars[i-1] == '\n' {
+        b'>' if line_start => {
           let mut level: usize = 1;
-          while is_next_eq(level - 1 + i, &chars, '>') {
+          while is_next_eq(level - 1 + i, bytes, b'>') {
             level += 1;
           }
-          if i+level >= chars.len() || (chars[i+level] == ' ' || chars[i+level] == '\n') {
+          if i+level >= bytes.len() || (bytes[i+level] == b' ' || bytes[i+level] == b'\n') {
             data.flush_and_add_token(Token::BlockQuote(level.try_into().unwrap())); // never overflows
             i += level + 1;
             done = true;
           }
         }
-      }
 
-      '#' => 'sharp_match: {
-        if is_next_eq(i, &chars, '#') {
-          // color code
-          if chars.len() > i+8 {
-            let mut ok = true;
-            for j in 2..8 {
-              let v = vec!['0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'A', 'b', 'B', 'c', 'C', 'd', 'D', 'e', 'E', 'f', 'F'];
-              if !v.contains(&chars[i+j]) {
-                ok = false;
-              }
-            }
-            if chars[i+8] != '|' { ok = false; }
+        b'+' if line_start => {
+          let mut level: usize = 1;
+          while is_next_eq(level - 1 + i, bytes, b'+') {
+            level += 1;
+          }
+          if level <= 6 && is_next_eq(level - 1 + i, bytes, b' ') {
+            data.flush_and_add_token(Token::Heading(level.try_into().unwrap())); // never overflows
+            data.heading_count += 1;
+            i += level + 1;
+            done = true;
+          }
+        }
 
-            if ok {
-              data.flush_and_add_token(Token::ColoredBeginColorCode(chars[i+2..i+8].iter().collect()));
-              i += 2 /* ## */ + 6 /* RGB */ + 1 /* | */;
-              done = true;
-              break 'sharp_match;
-            }
+        b'#' if is_next_eq(i, bytes, b'#') => 'sharp_match: {
+          // color code
+          if bytes.len() > i+8 && bytes[i+2..i+8].iter().all(u8::is_ascii_hexdigit) && bytes[i+8] == b'|' {
+            data.flush_and_add_token(Token::ColoredBeginColorCode(Cow::Borrowed(&s[i+2..i+8])));
+            i += 2 /* ## */ + 6 /* RGB */ + 1 /* | */;
+            done = true;
+            break 'sharp_match;
           }
 
           // FIXME: ast::WikidotColorを使用するように変更する
-          let wikidot_preset_colors = vec![
+          let wikidot_preset_colors = [
             "aqua",
             "black",
             "blue",
@@ -340,11 +553,9 @@ pub fn tokenize(s: String) -> Vec<Token> {
             "yellow",
           ];
 
-          let s: String = chars[i+2..std::cmp::min(i+2+8, chars.len())].iter().collect();
-
           for wikidot_preset_color_string in wikidot_preset_colors {
-            if s.starts_with(wikidot_preset_color_string) && s.chars().nth(wikidot_preset_color_string.len()) == Some('|') {
-              data.flush_and_add_token(Token::ColoredBeginColorName(String::from(wikidot_preset_color_string)));
+            if bytes[i+2..].starts_with(wikidot_preset_color_string.as_bytes()) && bytes.get(i+2+wikidot_preset_color_string.len()) == Some(&b'|') {
+              data.flush_and_add_token(Token::ColoredBeginColorName(Cow::Borrowed(wikidot_preset_color_string)));
               i += 2 + wikidot_preset_color_string.len() + 1;
               done = true;
               break 'sharp_match;
@@ -355,32 +566,73 @@ pub fn tokenize(s: String) -> Vec<Token> {
           i+=2;
           done = true;
         }
-      }
 
-      '\n' => {
-        data.flush_and_add_token(Token::NewLine);
-        i += 1;
-        done = true;
-      }
+        b':' if line_start && is_next_eq(i, bytes, b' ') => {
+          data.flush_and_add_token(Token::DefinitionTerm);
+          self.in_definition_term = true;
+          i += 2;
+          done = true;
+        }
+
+        b' ' if self.in_definition_term && is_next_eq(i, bytes, b':') && bytes.get(i+2).is_none_or(|b| *b == b' ' || *b == b'\n') => {
+          data.flush_and_add_token(Token::DefinitionSeparator);
+          self.in_definition_term = false;
+          i += if bytes.get(i+2) == Some(&b' ') { 3 } else { 2 };
+          done = true;
+        }
 
-      _ => {}
-    };
+        // 行末の " _" は改行. 次の行が続くなら同じ行として読む
+        b' ' if is_next_eq(i, bytes, b'_') && bytes.get(i+2).is_none_or(|b| *b == b'\n') => {
+          data.flush_and_add_token(Token::LineBreak);
+          i += 2;
+          if bytes.get(i+1).is_some_and(|b| *b != b'\n') {
+            i += 1;
+            self.continued_line = Some(i);
+          }
+          done = true;
+        }
+
+        b'\n' => {
+          self.in_definition_term = false;
+          data.flush_and_add_token(Token::NewLine);
+          i += 1;
+          done = true;
+        }
+
+        _ => {}
+      };
+
+      if !done {
+        let len = char_len(s, i);
+        data.add_slice(i, i+len);
+        i += len;
+      }
 
-    if !done {
-      data.add_char(chars[i]);
-      i += 1;
     }
 
+    self.i = i;
+    true
   }
+}
+
+impl<'a> Iterator for Tokenizer<'a> {
+  type Item = Token<'a>;
 
-  data.get_value()
+  fn next(&mut self) -> Option<Token<'a>> {
+    while self.data.res.is_empty() && self.step() {}
+    self.data.res.pop_front()
+  }
 }
 
 #[cfg(test)]
 mod test {
   use super::Token;
-  fn tokenize(s: &str) -> Vec<super::Token> {
-    super::tokenize(String::from(s))
+  fn tokenize(s: &str) -> Vec<super::Token<'_>> {
+    super::tokenize(s)
+  }
+
+  fn page(s: &str) -> crate::link::PageLink {
+    crate::link::PageLink::parse(s).unwrap()
   }
 
   #[test]
@@ -392,7 +644,7 @@ mod test {
   fn test_bold() {
     assert_eq!(tokenize("**bolded_string**"), vec![
       Token::Bold,
-      Token::Text(String::from("bolded_string")),
+      Token::Text("bolded_string".into()),
       Token::Bold,
     ]);
   }
@@ -401,7 +653,7 @@ mod test {
   fn test_italics() {
     assert_eq!(tokenize("//Italic text//"), vec![
       Token::Italics,
-      Token::Text(String::from("Italic text")),
+      Token::Text("Italic text".into()),
       Token::Italics,
     ]);
   }
@@ -410,7 +662,7 @@ mod test {
   fn test_underline() {
     assert_eq!(tokenize("__Underlined text__"), vec![
       Token::Underline,
-      Token::Text(String::from("Underlined text")),
+      Token::Text("Underlined text".into()),
       Token::Underline,
     ]);
   }
@@ -419,7 +671,7 @@ mod test {
   fn test_strikethrough() {
     assert_eq!(tokenize("--Outdated Containment Procedure--"), vec![
       Token::Strikethrough,
-      Token::Text(String::from("Outdated Containment Procedure")),
+      Token::Text("Outdated Containment Procedure".into()),
       Token::Strikethrough,
     ]);
   }
@@ -428,7 +680,7 @@ mod test {
   fn test_monospaced() {
     assert_eq!(tokenize("{{Monospaced Text}}"), vec![
       Token::MonospacedOpen,
-      Token::Text(String::from("Monospaced Text")),
+      Token::Text("Monospaced Text".into()),
       Token::MonospacedClose,
     ]);
   }
@@ -436,101 +688,374 @@ mod test {
   #[test]
   fn test_superscript() {
     assert_eq!(tokenize("Super^^scripted^^text"), vec![
-      Token::Text(String::from("Super")),
+      Token::Text("Super".into()),
       Token::Superscript,
-      Token::Text(String::from("scripted")),
+      Token::Text("scripted".into()),
       Token::Superscript,
-      Token::Text(String::from("text")),
+      Token::Text("text".into()),
     ]);
   }
 
   #[test]
   fn test_subscript() {
     assert_eq!(tokenize("Sub,,scripted,,text"), vec![
-      Token::Text(String::from("Sub")),
+      Token::Text("Sub".into()),
       Token::Subscript,
-      Token::Text(String::from("scripted")),
+      Token::Text("scripted".into()),
       Token::Subscript,
-      Token::Text(String::from("text")),
+      Token::Text("text".into()),
     ]);
   }
 
   #[test]
   fn test_elementbegin_and_elementend() {
     assert_eq!(tokenize(r#"aaa[[span id="box" checked]]Hey![[/span]]bbb"#), vec![
-      Token::Text(String::from("aaa")),
+      Token::Text("aaa".into()),
       Token::ElementBegin {
-        name: String::from("span"),
+        name: "span".into(),
         attributes: vec![
-          (String::from("id"), String::from("box")),
-          (String::from(""), String::from("checked"))
+          ("id".into(), "box".into()),
+          ("".into(), "checked".into())
+        ], arguments: vec![],
+      },
+      Token::Text("Hey!".into()),
+      Token::ElementEnd("span".into()),
+      Token::Text("bbb".into()),
+    ])
+  }
+
+  #[test]
+  fn test_elementbegin_quoted_attributes() {
+    assert_eq!(tokenize(r#"[[span style="color: red; font-weight: bold" title="a]]b"]]x"#), vec![
+      Token::ElementBegin {
+        name: "span".into(),
+        attributes: vec![
+          ("style".into(), "color: red; font-weight: bold".into()),
+          ("title".into(), "a]]b".into()),
         ],
+        arguments: vec![],
       },
-      Token::Text(String::from("Hey!")),
-      Token::ElementEnd(String::from("span")),
-      Token::Text(String::from("bbb")),
+      Token::Text("x".into()),
     ])
   }
 
+  #[test]
+  fn test_elementbegin_include_arguments() {
+    assert_eq!(tokenize("[[include page | a=1 | b = two words]]"), vec![
+      Token::ElementBegin {
+        name: "include".into(),
+        attributes: vec![("".into(), "page".into())],
+        arguments: vec![
+          ("a".into(), "1".into()),
+          ("b".into(), "two words".into()),
+        ],
+      },
+    ])
+  }
+
+  #[test]
+  fn test_breaks() {
+    assert_eq!(tokenize("a\n----\n-----b\nc _\nd _\n\ne _"), vec![
+      Token::Text("a".into()),
+      Token::NewLine,
+      Token::HorizontalRule,
+      Token::NewLine,
+      Token::Text("-----b".into()),
+      Token::NewLine,
+      Token::Text("c".into()),
+      Token::LineBreak,
+      Token::Text("d".into()),
+      Token::LineBreak,
+      Token::NewLine,
+      Token::NewLine,
+      Token::Text("e".into()),
+      Token::LineBreak,
+    ]);
+  }
+
+  #[test]
+  fn test_continued_line() {
+    // " _" で続く行の先頭は見出しなどにならない
+    assert_eq!(tokenize("+ a _\n+ b _\n> c _\n----\n+ d"), vec![
+      Token::Heading(std::num::NonZeroUsize::new(1).unwrap()),
+      Token::Text("a".into()),
+      Token::LineBreak,
+      Token::Text("+ b".into()),
+      Token::LineBreak,
+      Token::Text("> c".into()),
+      Token::LineBreak,
+      Token::Text("----".into()),
+      Token::NewLine,
+      Token::Heading(std::num::NonZeroUsize::new(1).unwrap()),
+      Token::Text("d".into()),
+    ]);
+  }
+
+  #[test]
+  fn test_module_css() {
+    assert_eq!(tokenize("[[module CSS]]\n.a { **b**: --c; }\n[[/module]]x"), vec![
+      Token::Stylesheet("\n.a { **b**: --c; }\n".into()),
+      Token::Text("x".into()),
+    ]);
+    // 閉じられていなければ通常の要素として読む
+    assert_eq!(tokenize("[[module CSS]]**a**"), vec![
+      Token::ElementBegin { name: "module".into(), attributes: vec![("".into(), "CSS".into())], arguments: vec![] },
+      Token::Bold,
+      Token::Text("a".into()),
+      Token::Bold,
+    ]);
+  }
+
+  #[test]
+  fn test_module() {
+    assert_eq!(tokenize("[[module Rate]]\n[[module ListPages category=\"news\" | x=1]]\n**a**\n[[/module]]"), vec![
+      Token::Module { name: "Rate".into(), args: vec![], body: None, position: 0 },
+      Token::NewLine,
+      Token::Module {
+        name: "ListPages".into(),
+        args: vec![("category".into(), "news".into()), ("x".into(), "1".into())],
+        body: Some("\n**a**\n".into()),
+        position: 16,
+      },
+    ]);
+  }
+
+  #[test]
+  fn test_definition_list() {
+    assert_eq!(tokenize(": a : b : c\n:x\n: d"), vec![
+      Token::DefinitionTerm,
+      Token::Text("a".into()),
+      Token::DefinitionSeparator,
+      Token::Text("b : c".into()),
+      Token::NewLine,
+      Token::Text(":x".into()),
+      Token::NewLine,
+      Token::DefinitionTerm,
+      Token::Text("d".into()),
+    ]);
+  }
+
+  #[test]
+  fn test_anchors() {
+    assert_eq!(tokenize("[[# top]][#top Back]"), vec![
+      Token::ElementBegin { name: "#".into(), attributes: vec![("".into(), "top".into())], arguments: vec![] },
+      Token::NamedLink { link: "#top".into(), name: "Back".into(), new_tab: false, source: "[#top Back]".into() },
+    ]);
+
+    let mut tokenizer = super::Tokenizer::new("[[# a]]\n+ Title\n[[# a]][[# toc0]][[# toc1]]");
+    tokenizer.by_ref().for_each(drop);
+    assert_eq!(tokenizer.into_diagnostics(), vec![
+      crate::diagnostic::Diagnostic::DuplicateAnchor { name: String::from("a"), position: 16 },
+      crate::diagnostic::Diagnostic::DuplicateAnchor { name: String::from("toc0"), position: 23 },
+    ]);
+  }
+
+  #[test]
+  fn test_backslash_at_end() {
+    assert_eq!(tokenize("a\\"), vec![Token::Text("a\n".into())]);
+    assert_eq!(tokenize("\\"), vec![Token::Text("\n".into())]);
+  }
+
+  #[test]
+  fn test_label_diagnostics() {
+    // ラベルの中の問題もページ上の位置で報告する
+    let mut tokenizer = super::Tokenizer::new(r#"[[# top]] [[[page|[[span class="a" Class="b"]]x]]] [[[page|[[# top]]y]]]"#);
+    tokenizer.by_ref().for_each(drop);
+    assert_eq!(tokenizer.into_diagnostics(), vec![
+      crate::diagnostic::Diagnostic::DuplicateAttribute { element: String::from("span"), name: String::from("class"), position: 18 },
+      crate::diagnostic::Diagnostic::DuplicateAnchor { name: String::from("top"), position: 59 },
+    ]);
+  }
+
+  #[test]
+  fn test_elementbegin_duplicate_attribute() {
+    let mut tokenizer = super::Tokenizer::new(r#"ab[[span class="x" Class="y"]]"#);
+    let tokens: Vec<Token> = tokenizer.by_ref().collect();
+    let diagnostics = tokenizer.into_diagnostics();
+    assert_eq!(tokens[1], Token::ElementBegin {
+      name: "span".into(),
+      attributes: vec![("class".into(), "x".into())],
+      arguments: vec![],
+    });
+    assert_eq!(diagnostics, vec![crate::diagnostic::Diagnostic::DuplicateAttribute {
+      element: String::from("span"),
+      name: String::from("class"),
+      position: 2,
+    }]);
+  }
+
+  #[test]
+  fn test_unterminated_brackets() {
+    assert_eq!(tokenize("a [[span b"), vec![Token::Text("a [[span b".into())]);
+    assert_eq!(tokenize("[[[page\n]"), vec![
+      Token::Text("[[[page".into()),
+      Token::NewLine,
+      Token::Text("]".into()),
+    ]);
+    assert_eq!(tokenize("[mailto:a@example.com a"), vec![Token::Text("[mailto:a@example.com a".into())]);
+  }
+
+  #[test]
+  fn test_protected_text() {
+    assert_eq!(tokenize("see http://example.com//a__b, then //it//"), vec![
+      Token::Text("see ".into()),
+      Token::NamedLink { link: "http://example.com//a__b".into(), name: "http://example.com//a__b".into(), new_tab: false, source: "http://example.com//a__b".into() },
+      Token::Text(", then ".into()),
+      Token::Italics,
+      Token::Text("it".into()),
+      Token::Italics,
+    ]);
+    assert_eq!(tokenize("foo--bar --baz--"), vec![
+      Token::Text("foo--bar ".into()),
+      Token::Strikethrough,
+      Token::Text("baz".into()),
+      Token::Strikethrough,
+    ]);
+    // 水平線にならない `-` の並びは文字のまま
+    assert_eq!(tokenize("-----x a --- b--c"), vec![
+      Token::Text("-----x a --- b--c".into()),
+    ]);
+  }
+
+  #[test]
+  fn test_borrowed_text() {
+    use std::borrow::Cow;
+    let tokens = tokenize("あいう**えお**");
+    assert_eq!(tokens, vec![Token::Text("あいう".into()), Token::Bold, Token::Text("えお".into()), Token::Bold]);
+    assert!(matches!(tokens[0], Token::Text(Cow::Borrowed(_))));
+
+    // only unescaped text is allocated
+    let tokens = tokenize(r"a\*\*b");
+    assert_eq!(tokens, vec![Token::Text("a**b".into())]);
+    assert!(matches!(tokens[0], Token::Text(Cow::Owned(_))));
+    let tokens = tokenize("[[span class=\"a\"]]");
+    assert!(matches!(&tokens[0], Token::ElementBegin { name: Cow::Borrowed(_), attributes, .. } if matches!(attributes[0].1, Cow::Borrowed(_))));
+  }
+
+  #[test]
+  fn test_peek() {
+    let mut tokenizer = super::Tokenizer::new("a**b");
+    assert_eq!(tokenizer.peek_nth(1), Some(&Token::Bold));
+    assert_eq!(tokenizer.peek(), Some(&Token::Text("a".into())));
+    assert_eq!(tokenizer.next(), Some(Token::Text("a".into())));
+    assert_eq!(tokenizer.next(), Some(Token::Bold));
+    assert_eq!(tokenizer.peek_nth(1), None);
+    assert_eq!(tokenizer.next(), Some(Token::Text("b".into())));
+    assert_eq!(tokenizer.next(), None);
+  }
+
   #[test]
   fn test_colored_colorcode() {
     assert_eq!(tokenize("bbb##ff00ff|Material Error##aaa"), vec![
-      Token::Text(String::from("bbb")),
-      Token::ColoredBeginColorCode(String::from("ff00ff")),
-      Token::Text(String::from("Material Error")),
+      Token::Text("bbb".into()),
+      Token::ColoredBeginColorCode("ff00ff".into()),
+      Token::Text("Material Error".into()),
       Token::ColoredEnd,
-      Token::Text(String::from("aaa")),
+      Token::Text("aaa".into()),
     ])
   }
 
   #[test]
   fn test_colored_colorname() {
     assert_eq!(tokenize("bbb##green|Test Passed##aaa"), vec![
-      Token::Text(String::from("bbb")),
-      Token::ColoredBeginColorName(String::from("green")),
-      Token::Text(String::from("Test Passed")),
+      Token::Text("bbb".into()),
+      Token::ColoredBeginColorName("green".into()),
+      Token::Text("Test Passed".into()),
       Token::ColoredEnd,
-      Token::Text(String::from("aaa")),
+      Token::Text("aaa".into()),
     ]);
   }
 
   #[test]
   fn test_namedlink() {
     assert_eq!(tokenize("[https://example.com example link]aa"), vec![
-      Token::NamedLink { link: String::from("https://example.com"), name: String::from("example link") },
-      Token::Text(String::from("aa")),
+      Token::NamedLink { link: "https://example.com".into(), name: "example link".into(), new_tab: false, source: "[https://example.com example link]".into() },
+      Token::Text("aa".into()),
     ])
   }
 
+  #[test]
+  fn test_namedlink_other_scheme() {
+    assert_eq!(tokenize("[mailto:someone@example.com mail me]"), vec![
+      Token::NamedLink { link: "mailto:someone@example.com".into(), name: "mail me".into(), new_tab: false, source: "[mailto:someone@example.com mail me]".into() },
+    ]);
+    assert_eq!(tokenize("[not a link]"), vec![
+      Token::Text("[not a link]".into()),
+    ]);
+  }
+
   #[test]
   fn test_pagelink() {
     assert_eq!(tokenize("[[[example|hello]]]"), vec![
-      Token::PageLink { link: String::from("example"), name: String::from("hello") },
+      Token::PageLink { link: "example".into(), target: page("example"), name: "hello".into(), new_tab: false },
     ])
   }
 
+  #[test]
+  fn test_links_without_label() {
+    assert_eq!(tokenize("[https://example.com] (https://example.com/a_(b)). "), vec![
+      Token::NamedLink { link: "https://example.com".into(), name: "https://example.com".into(), new_tab: false, source: "[https://example.com]".into() },
+      Token::Text(" (".into()),
+      Token::NamedLink { link: "https://example.com/a_(b)".into(), name: "https://example.com/a_(b)".into(), new_tab: false, source: "https://example.com/a_(b)".into() },
+      Token::Text("). ".into()),
+    ]);
+    assert_eq!(tokenize("[mailto:a@example.com]"), vec![
+      Token::NamedLink { link: "mailto:a@example.com".into(), name: "mailto:a@example.com".into(), new_tab: false, source: "[mailto:a@example.com]".into() },
+    ]);
+  }
+
+  #[test]
+  fn test_pagelink_target() {
+    assert_eq!(tokenize("[[[SCP-173]]] [[[:site:cat:page#a | b]]]"), vec![
+      Token::PageLink { link: "SCP-173".into(), target: page("scp-173"), name: "".into(), new_tab: false },
+      Token::Text(" ".into()),
+      Token::PageLink { link: ":site:cat:page#a ".into(), target: page(":site:cat:page#a"), name: " b".into(), new_tab: false },
+    ]);
+    assert!(!tokenize("[[[ |a]]]").iter().any(|token| matches!(token, Token::PageLink { .. })));
+  }
+
+  #[test]
+  fn test_new_tab_links() {
+    assert_eq!(tokenize("[*https://example.com example] [[[*example|hello]]]"), vec![
+      Token::NamedLink { link: "https://example.com".into(), name: "example".into(), new_tab: true, source: "[*https://example.com example]".into() },
+      Token::Text(" ".into()),
+      Token::PageLink { link: "example".into(), target: page("example"), name: "hello".into(), new_tab: true },
+    ]);
+    assert_eq!(tokenize("[*not a link]"), vec![Token::Text("[*not a link]".into())]);
+  }
+
   #[test]
   fn test_asterisk() {
     assert_eq!(tokenize("hey*ho"), vec![
-      Token::Text(String::from("hey*ho")),
+      Token::Text("hey*ho".into()),
     ])
   }
 
   #[test]
   fn test_quoteblock() {
     assert_eq!(tokenize("> One\n>> Two\n>> Three\n> Four\nFive"), vec![
-      Token::BlockQuote(std::num::NonZeroUsize::new(1).unwrap()), Token::Text(String::from("One")),
-      Token::NewLine, Token::BlockQuote(std::num::NonZeroUsize::new(2).unwrap()), Token::Text(String::from("Two")),
-      Token::NewLine, Token::BlockQuote(std::num::NonZeroUsize::new(2).unwrap()), Token::Text(String::from("Three")),
-      Token::NewLine, Token::BlockQuote(std::num::NonZeroUsize::new(1).unwrap()), Token::Text(String::from("Four")),
-      Token::NewLine, Token::Text(String::from("Five")),
+      Token::BlockQuote(std::num::NonZeroUsize::new(1).unwrap()), Token::Text("One".into()),
+      Token::NewLine, Token::BlockQuote(std::num::NonZeroUsize::new(2).unwrap()), Token::Text("Two".into()),
+      Token::NewLine, Token::BlockQuote(std::num::NonZeroUsize::new(2).unwrap()), Token::Text("Three".into()),
+      Token::NewLine, Token::BlockQuote(std::num::NonZeroUsize::new(1).unwrap()), Token::Text("Four".into()),
+      Token::NewLine, Token::Text("Five".into()),
+    ]);
+  }
+
+  #[test]
+  fn test_heading() {
+    assert_eq!(tokenize("+ One\n+++ Three\n+++++++ Seven\na + b"), vec![
+      Token::Heading(std::num::NonZeroUsize::new(1).unwrap()), Token::Text("One".into()),
+      Token::NewLine, Token::Heading(std::num::NonZeroUsize::new(3).unwrap()), Token::Text("Three".into()),
+      Token::NewLine, Token::Text("+++++++ Seven".into()),
+      Token::NewLine, Token::Text("a + b".into()),
     ]);
   }
 
   #[test]
   fn test_escape_parsing() {
     assert_eq!(tokenize("@@**Should not be bolded**@@"), vec![
-      Token::Text(String::from("**Should not be bolded**")),
+      Token::Text("**Should not be bolded**".into()),
     ]);
   }
 }
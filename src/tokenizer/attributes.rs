@@ -0,0 +1,253 @@
+use std::borrow::Cow;
+
+/// `[[name attr="value" ... | arg=value | ...]]` split into its parts. Values borrow from the source unless they contain escapes.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ElementSpecifier<'a> {
+  pub name: Cow<'a, str>,
+  /// Space-separated attributes. Unnamed values have an empty key, e.g. `[[tab Title]]`.
+  pub attributes: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+  /// `|`-separated arguments, e.g. `[[include page | key=value]]`.
+  pub arguments: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+  /// Names which appeared more than once. Only the first value is kept.
+  pub duplicates: Vec<String>,
+}
+
+fn is_quote(c: char) -> bool {
+  c == '"' || c == '\''
+}
+
+fn trim(s: Cow<'_, str>) -> Cow<'_, str> {
+  match s {
+    Cow::Borrowed(v) => Cow::Borrowed(v.trim()),
+    Cow::Owned(v) if v.trim().len() == v.len() => Cow::Owned(v),
+    Cow::Owned(v) => Cow::Owned(v.trim().to_string()),
+  }
+}
+
+fn to_lowercase(s: Cow<'_, str>) -> Cow<'_, str> {
+  if s.chars().any(char::is_uppercase) {
+    Cow::Owned(s.to_lowercase())
+  } else {
+    s
+  }
+}
+
+struct Lexer<'a> {
+  src: &'a str,
+  at: usize, // byte offset
+}
+
+impl<'a> Lexer<'a> {
+  fn peek(&self) -> Option<char> {
+    self.src[self.at..].chars().next()
+  }
+
+  fn skip_whitespace(&mut self) {
+    while let Some(c) = self.peek() && c.is_whitespace() {
+      self.at += c.len_utf8();
+    }
+  }
+
+  /// Reads until `stop` returns true, resolving backslash escapes.
+  fn read_until(&mut self, stop: impl Fn(char) -> bool) -> Cow<'a, str> {
+    let src = self.src;
+    let begin = self.at;
+    let mut owned: Option<String> = None;
+
+    while let Some(c) = self.peek() {
+      if c == '\\' {
+        let res = owned.get_or_insert_with(|| src[begin..self.at].to_string());
+        self.at += 1;
+        if let Some(next) = self.peek() {
+          res.push(next);
+          self.at += next.len_utf8();
+        }
+        continue;
+      }
+      if stop(c) {
+        break;
+      }
+      if let Some(res) = &mut owned {
+        res.push(c);
+      }
+      self.at += c.len_utf8();
+    }
+
+    match owned {
+      Some(res) => Cow::Owned(res),
+      None => Cow::Borrowed(&src[begin..self.at]),
+    }
+  }
+
+  /// Reads a quoted value. The current character must be the opening quote.
+  fn read_quoted(&mut self) -> Cow<'a, str> {
+    let quote = self.peek().unwrap();
+    self.at += 1;
+    let res = self.read_until(|c| c == quote);
+    if self.peek() == Some(quote) {
+      self.at += 1; // closing quote
+    }
+    res
+  }
+
+  /// Reads an attribute value: quoted, or until whitespace.
+  fn read_value(&mut self) -> Cow<'a, str> {
+    match self.peek() {
+      Some(c) if is_quote(c) => self.read_quoted(),
+      _ => self.read_until(|c| c.is_whitespace() || c == '|'),
+    }
+  }
+
+  /// Reads an include argument: quoted, or until the next `|`.
+  fn read_argument_value(&mut self) -> Cow<'a, str> {
+    match self.peek() {
+      Some(c) if is_quote(c) => {
+        let res = self.read_quoted();
+        self.read_until(|c| c == '|');
+        res
+      }
+      _ => trim(self.read_until(|c| c == '|')),
+    }
+  }
+}
+
+fn push_named<'a>(list: &mut Vec<(Cow<'a, str>, Cow<'a, str>)>, duplicates: &mut Vec<String>, key: Cow<'a, str>, value: Cow<'a, str>) {
+  if list.iter().any(|(k, _)| *k == key) {
+    if !duplicates.iter().any(|v| *v == key) {
+      duplicates.push(key.into_owned());
+    }
+  } else {
+    list.push((key, value));
+  }
+}
+
+/// Parses the inside of `[[...]]`. Backslash escapes are resolved here.
+pub fn parse_element_specifier(src: &str) -> ElementSpecifier<'_> {
+  let mut lexer = Lexer { src, at: 0 };
+  let mut res = ElementSpecifier::default();
+
+  lexer.skip_whitespace();
+  res.name = lexer.read_until(|c| c.is_whitespace() || c == '|');
+
+  let mut in_arguments = false;
+  loop {
+    lexer.skip_whitespace();
+    let Some(c) = lexer.peek() else {
+      break;
+    };
+
+    if c == '|' {
+      lexer.at += 1;
+      in_arguments = true;
+      continue;
+    }
+
+    if in_arguments {
+      if is_quote(c) {
+        let value = lexer.read_argument_value();
+        res.arguments.push((Cow::Borrowed(""), value));
+        continue;
+      }
+
+      let key = lexer.read_until(|c| c == '=' || c == '|');
+      if lexer.peek() == Some('=') {
+        lexer.at += 1;
+        lexer.skip_whitespace();
+        let value = lexer.read_argument_value();
+        push_named(&mut res.arguments, &mut res.duplicates, to_lowercase(trim(key)), value);
+      } else {
+        res.arguments.push((Cow::Borrowed(""), trim(key)));
+      }
+    } else {
+      if is_quote(c) {
+        let value = lexer.read_quoted();
+        res.attributes.push((Cow::Borrowed(""), value));
+        continue;
+      }
+
+      let key = lexer.read_until(|c| c.is_whitespace() || c == '=' || c == '|');
+      let before_space = lexer.at;
+      lexer.skip_whitespace();
+      if lexer.peek() == Some('=') {
+        lexer.at += 1;
+        lexer.skip_whitespace();
+        let value = lexer.read_value();
+        push_named(&mut res.attributes, &mut res.duplicates, to_lowercase(key), value);
+      } else {
+        lexer.at = before_space;
+        res.attributes.push((Cow::Borrowed(""), key));
+      }
+    }
+  }
+
+  res
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(s: &str) -> ElementSpecifier<'_> {
+    parse_element_specifier(s)
+  }
+
+  fn pairs<'a>(v: &[(&'a str, &'a str)]) -> Vec<(Cow<'a, str>, Cow<'a, str>)> {
+    v.iter().map(|(k, v)| (Cow::Borrowed(*k), Cow::Borrowed(*v))).collect()
+  }
+
+  #[test]
+  fn test_quotes() {
+    let res = parse(r#"span style="color: red; font-weight: bold" class='a b' id=box checked"#);
+    assert_eq!(res.name, "span");
+    assert_eq!(res.attributes, pairs(&[
+      ("style", "color: red; font-weight: bold"),
+      ("class", "a b"),
+      ("id", "box"),
+      ("", "checked"),
+    ]));
+    assert!(res.arguments.is_empty());
+  }
+
+  #[test]
+  fn test_escaped_quote() {
+    let res = parse(r#"span title="say \"hi\"" data='it\'s'"#);
+    assert_eq!(res.attributes, pairs(&[("title", r#"say "hi""#), ("data", "it's")]));
+  }
+
+  #[test]
+  fn test_case_insensitive_names() {
+    let res = parse(r#"span STYLE = "color: red" Class="x""#);
+    assert_eq!(res.attributes, pairs(&[("style", "color: red"), ("class", "x")]));
+  }
+
+  #[test]
+  fn test_duplicates() {
+    let res = parse(r#"span class="a" CLASS="b" class="c""#);
+    assert_eq!(res.attributes, pairs(&[("class", "a")]));
+    assert_eq!(res.duplicates, vec![String::from("class")]);
+  }
+
+  #[test]
+  fn test_unnamed_values() {
+    let res = parse("tab Don't panic");
+    assert_eq!(res.attributes, pairs(&[("", "Don't"), ("", "panic")]));
+    let res = parse(r#"tab "Tab 1""#);
+    assert_eq!(res.attributes, pairs(&[("", "Tab 1")]));
+  }
+
+  #[test]
+  fn test_include_arguments() {
+    let res = parse("include component:box | title=Hello world | color = red |note|");
+    assert_eq!(res.name, "include");
+    assert_eq!(res.attributes, pairs(&[("", "component:box")]));
+    assert_eq!(res.arguments, pairs(&[("title", "Hello world"), ("color", "red"), ("", "note")]));
+  }
+
+  #[test]
+  fn test_borrowing() {
+    let res = parse(r#"span class="a b" title="say \"hi\"""#);
+    assert!(matches!(res.name, Cow::Borrowed(_)));
+    assert!(matches!(res.attributes[0].1, Cow::Borrowed(_)));
+    assert!(matches!(res.attributes[1].1, Cow::Owned(_)));
+  }
+}
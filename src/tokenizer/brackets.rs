@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+/// Positions of closing brackets and quotes, collected in one pass so that looking for the end of `[`, `[[` and `[[[`
+/// never rescans the page. Only the positions of these characters are stored, not a table per byte.
+/// Lookups from the tokenizer move forward and are O(1) amortized; following quoted values is O(log n) per quote.
+/// Positions are byte offsets.
+pub struct BracketIndex {
+  newlines: Positions,
+  close1: Positions, // `]`
+  close2: Positions, // `]]`
+  close3: Positions, // `]]]`
+  openers: Vec<usize>, // quotes which open an attribute value
+  double_quotes: Vec<usize>,
+  single_quotes: Vec<usize>,
+  // end of a specifier, searched from the position right after a quoted value
+  specifier_memo: HashMap<usize, Option<usize>>,
+  // (position the search started from, first `[[/module]]` after it)
+  module_end_memo: Option<(usize, Option<usize>)>,
+}
+
+pub const MODULE_END: &str = "[[/module]]";
+
+/// Sorted positions of one kind of character, with a cursor for lookups which move forward.
+#[derive(Default)]
+struct Positions {
+  list: Vec<usize>,
+  cursor: usize,
+}
+
+impl Positions {
+  /// First position at or after `at`. Cheap if `at` does not go backwards between calls.
+  fn next(&mut self, at: usize) -> Option<usize> {
+    if self.cursor > 0 && self.list[self.cursor - 1] >= at {
+      self.cursor = self.list.partition_point(|v| *v < at);
+    }
+    while self.cursor < self.list.len() && self.list[self.cursor] < at {
+      self.cursor += 1;
+    }
+    self.list.get(self.cursor).copied()
+  }
+}
+
+/// First position at or after `at`, without moving any cursor.
+fn next_in(list: &[usize], at: usize) -> Option<usize> {
+  list.get(list.partition_point(|v| *v < at)).copied()
+}
+
+fn is_quote(c: u8) -> bool {
+  c == b'"' || c == b'\''
+}
+
+impl BracketIndex {
+  pub fn new(s: &str) -> Self {
+    let bytes = s.as_bytes();
+    let len = bytes.len();
+    let unescaped = |at: usize| at == 0 || bytes[at-1] != b'\\';
+    let run_of = |at: usize, n: usize| at + n <= len && bytes[at..at+n].iter().all(|c| *c == b']');
+
+    let mut res = Self {
+      newlines: Positions::default(),
+      close1: Positions::default(),
+      close2: Positions::default(),
+      close3: Positions::default(),
+      openers: vec![],
+      double_quotes: vec![],
+      single_quotes: vec![],
+      specifier_memo: HashMap::new(),
+      module_end_memo: None,
+    };
+
+    for (at, c) in bytes.iter().enumerate() {
+      match c {
+        b'\n' => { res.newlines.list.push(at); }
+        b']' if unescaped(at) => {
+          res.close1.list.push(at);
+          if run_of(at, 2) { res.close2.list.push(at); }
+          if run_of(at, 3) { res.close3.list.push(at); }
+        }
+        b'"' | b'\'' => {
+          if unescaped(at) {
+            if *c == b'"' { res.double_quotes.push(at); } else { res.single_quotes.push(at); }
+          }
+          // a quote opens a value only at its beginning, so that `[[tab Don't]]` is not affected
+          if s[..at].chars().next_back().is_some_and(|c| c == '=' || c == '|' || c.is_whitespace()) {
+            res.openers.push(at);
+          }
+        }
+        _ => {}
+      }
+    }
+
+    res
+  }
+
+  /// `found` if no newline comes before it.
+  fn in_line(found: Option<usize>, newline: Option<usize>) -> Option<usize> {
+    let found = found?;
+    match newline {
+      Some(newline) if newline < found => None,
+      _ => Some(found),
+    }
+  }
+
+  /// The `]` closing the `[` at `at`.
+  pub fn named_link_end(&mut self, at: usize) -> Option<usize> {
+    Self::in_line(self.close1.next(at + 1), self.newlines.next(at))
+  }
+
+  /// The `]]]` closing the `[[[` at `at`.
+  pub fn page_link_end(&mut self, at: usize) -> Option<usize> {
+    Self::in_line(self.close3.next(at + 3), self.newlines.next(at))
+  }
+
+  /// The first `[[/module]]` at or after `from`. The last result is reused, so unclosed modules do not rescan the page.
+  pub fn module_end(&mut self, s: &str, from: usize) -> Option<usize> {
+    if let Some((searched, res)) = self.module_end_memo && searched <= from && res.is_none_or(|v| v >= from) {
+      return res;
+    }
+    let res = s[from..].find(MODULE_END).map(|v| v + from);
+    self.module_end_memo = Some((from, res));
+    res
+  }
+
+  /// The quote closing a value opened before `at`. A value does not go past the end of the line.
+  fn closing_quote(&self, quote: u8, at: usize) -> Option<usize> {
+    let found = if quote == b'"' {
+      next_in(&self.double_quotes, at)
+    } else {
+      next_in(&self.single_quotes, at)
+    };
+    Self::in_line(found, next_in(&self.newlines.list, at))
+  }
+
+  /// The `]]` closing the element specifier of the `[[` at `at`. `]]` inside a quoted value does not close it.
+  /// Falls back to the first `]]` if a quote is not closed in its line.
+  pub fn specifier_end(&mut self, s: &str, at: usize) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let begin = at + 2;
+    let plain = self.close2.next(begin)?;
+
+    let mut at = begin;
+    if let Some(c) = bytes.get(begin) && is_quote(*c) {
+      match self.closing_quote(*c, begin + 1) {
+        Some(end) => { at = end + 1; }
+        None => { return Some(plain); }
+      }
+    }
+
+    Some(self.quoted_end(bytes, at).unwrap_or(plain))
+  }
+
+  /// Follows the quoted values from `from`. `None` if there is no `]]` or a quote is not closed in its line.
+  fn quoted_end(&mut self, bytes: &[u8], from: usize) -> Option<usize> {
+    let mut visited = vec![];
+    let mut at = from;
+
+    let res = loop {
+      if let Some(v) = self.specifier_memo.get(&at) {
+        break *v;
+      }
+      visited.push(at);
+
+      let Some(close) = next_in(&self.close2.list, at) else {
+        break None;
+      };
+      match next_in(&self.openers, at) {
+        Some(opener) if opener < close => {
+          match self.closing_quote(bytes[opener], opener + 1) {
+            Some(end) => { at = end + 1; }
+            None => { break None; }
+          }
+        }
+        _ => { break Some(close); }
+      }
+    };
+
+    for v in visited {
+      self.specifier_memo.insert(v, res);
+    }
+    res
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn specifier_end(s: &str) -> Option<usize> {
+    BracketIndex::new(s).specifier_end(s, 0)
+  }
+
+  #[test]
+  fn test_specifier_end() {
+    assert_eq!(specifier_end(r#"[[span title="a]]b"]]x"#), Some(19));
+    assert_eq!(specifier_end(r#"[[span title="a]]b"#), Some(15));
+    assert_eq!(specifier_end("[[tab Don't]]"), Some(11));
+    assert_eq!(specifier_end(r"[[a\]]]]"), Some(5));
+    assert_eq!(specifier_end("[[span"), None);
+    assert_eq!(specifier_end("[[tab　'あ]]'あ]]"), Some(18)); // byte offsets, the quote follows an ideographic space
+  }
+
+  #[test]
+  fn test_module_end() {
+    let s = "[[/module]] [[/module]]";
+    let mut index = BracketIndex::new(s);
+    assert_eq!(index.module_end(s, 0), Some(0));
+    assert_eq!(index.module_end(s, 1), Some(12));
+    assert_eq!(index.module_end(s, 13), None);
+    assert_eq!(index.module_end(s, 20), None);
+  }
+
+  #[test]
+  fn test_link_end() {
+    let mut index = BracketIndex::new("[a]\n[b\n]");
+    assert_eq!(index.named_link_end(0), Some(2));
+    assert_eq!(index.named_link_end(4), None);
+
+    assert_eq!(BracketIndex::new("[[[a]]]").page_link_end(0), Some(4));
+  }
+
+  #[test]
+  fn test_quote_not_closed_in_line() {
+    let s = "[[a x=\"]]\n[[b y=\"]]\n\"";
+    let mut index = BracketIndex::new(s);
+    // the quote in the next line does not close the value, so each tag ends at its own `]]`
+    assert_eq!(index.specifier_end(s, 0), Some(7));
+    assert_eq!(index.specifier_end(s, 10), Some(17));
+
+    assert_eq!(specifier_end("[[a x='b\n']] c]]"), Some(10));
+  }
+}
@@ -0,0 +1,337 @@
+/// Rules applied when a URL written in a page is turned into a `Url`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UrlPolicy {
+  /// Schemes which may appear in the output, in lowercase.
+  pub allowed_schemes: Vec<String>,
+  /// Absolute URL which relative URLs are resolved against, e.g. `https://example.wikidot.com/`.
+  /// Relative URLs are kept as they are if this is `None`.
+  pub base: Option<String>,
+}
+
+impl Default for UrlPolicy {
+  fn default() -> Self {
+    Self {
+      allowed_schemes: vec![String::from("http"), String::from("https"), String::from("mailto")],
+      base: None,
+    }
+  }
+}
+
+impl UrlPolicy {
+  pub fn is_scheme_allowed(&self, scheme: &str) -> bool {
+    self.allowed_schemes.iter().any(|v| v.eq_ignore_ascii_case(scheme))
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UrlError {
+  Empty,
+  InvalidCharacter,
+  SchemeNotAllowed(String),
+}
+
+/// A validated URL. Absolute URLs only have allowed schemes, and the string is percent-encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Url {
+  href: String,
+  scheme: Option<String>,
+}
+
+/// Length of the scheme at the start of `bytes`, if it is followed by `:`.
+fn scheme_len(bytes: &[u8]) -> Option<usize> {
+  if !bytes.first().is_some_and(u8::is_ascii_alphabetic) {
+    return None;
+  }
+  let len = bytes.iter().position(|b| !(b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-' | b'.')))?;
+  (bytes[len] == b':').then_some(len)
+}
+
+/// Splits `scheme:rest`. The scheme is lowercased.
+fn split_scheme(s: &str) -> Option<(String, &str)> {
+  let len = scheme_len(s.as_bytes())?;
+  Some((s[..len].to_ascii_lowercase(), &s[len+1..]))
+}
+
+/// Returns true if the string starts with a syntactically valid scheme, e.g. `https://...` or `mailto:...`.
+/// Whether the scheme is allowed is decided later by `Url::parse`.
+pub fn is_absolute_url(s: &str) -> bool {
+  split_scheme(s).is_some_and(|(_, rest)| !rest.is_empty() && !rest.contains(char::is_whitespace))
+}
+
+/// Byte length of the URL written at the start of `s` in running text, e.g. `https://example.com/a`.
+/// Only `scheme://` URLs are recognized. The URL ends at whitespace, and trailing punctuation
+/// and unbalanced closing parentheses are not included.
+pub fn bare_url_len(s: &str) -> Option<usize> {
+  let bytes = s.as_bytes();
+
+  // スキームは短いので, 長い単語を何度も走査しないよう打ち切る
+  let scheme_len = scheme_len(&bytes[..bytes.len().min(32)])?;
+  if !bytes[scheme_len..].starts_with(b"://") {
+    return None;
+  }
+
+  let start = scheme_len + 3;
+  let mut end = bytes[start..].iter().position(|b| b.is_ascii_whitespace() || b"<>\"[]|".contains(b)).map_or(bytes.len(), |v| start + v);
+
+  let mut open = bytes[start..end].iter().filter(|b| **b == b'(').count();
+  let mut close = bytes[start..end].iter().filter(|b| **b == b')').count();
+  while end > start {
+    match bytes[end-1] {
+      b'.' | b',' | b';' | b':' | b'!' | b'?' | b'\'' => { end -= 1; }
+      b')' if close > open => { end -= 1; close -= 1; }
+      b'(' => { end -= 1; open -= 1; }
+      _ => { break; }
+    }
+  }
+
+  (end > start).then_some(end)
+}
+
+fn is_allowed_in_url(b: u8) -> bool {
+  b.is_ascii_alphanumeric() || b"-._~:/?#[]@!$&'()*+,;=".contains(&b)
+}
+
+/// Percent-encodes the characters not allowed in a URL and uppercases the existing escapes.
+fn normalize_percent_encoding(s: &str) -> String {
+  let bytes = s.as_bytes();
+  let mut res = String::new();
+
+  let mut i = 0;
+  while i < bytes.len() {
+    if bytes[i] == b'%' {
+      if i+2 < bytes.len() && bytes[i+1].is_ascii_hexdigit() && bytes[i+2].is_ascii_hexdigit() {
+        res.push('%');
+        res.push(bytes[i+1].to_ascii_uppercase() as char);
+        res.push(bytes[i+2].to_ascii_uppercase() as char);
+        i += 3;
+        continue;
+      }
+      res += "%25";
+    } else if is_allowed_in_url(bytes[i]) {
+      res.push(bytes[i] as char);
+    } else {
+      res += &format!("%{:02X}", bytes[i]);
+    }
+    i += 1;
+  }
+
+  res
+}
+
+/// Removes `.` and `..` segments from an absolute path (RFC 3986, 5.2.4).
+fn remove_dot_segments(path: &str) -> String {
+  let mut segments: Vec<&str> = vec![];
+  let parts: Vec<&str> = path.split('/').skip(1).collect();
+
+  for (at, part) in parts.iter().enumerate() {
+    let is_last = at + 1 == parts.len();
+    match *part {
+      "." => { if is_last { segments.push(""); } }
+      ".." => {
+        segments.pop();
+        if is_last { segments.push(""); }
+      }
+      v => { segments.push(v); }
+    }
+  }
+
+  format!("/{}", segments.join("/"))
+}
+
+/// Splits a reference into its path and the following query and fragment.
+fn split_path(s: &str) -> (&str, &str) {
+  s.split_at(s.find(['?', '#']).unwrap_or(s.len()))
+}
+
+/// Resolves a relative reference against an absolute base URL (RFC 3986, 5.2.2).
+fn resolve(base: &str, reference: &str) -> Option<String> {
+  let (scheme, rest) = split_scheme(base)?;
+  let rest = rest.strip_prefix("//")?;
+  let (authority, rest) = rest.split_at(rest.find(['/', '?', '#']).unwrap_or(rest.len()));
+  let (base_path, _) = split_path(rest);
+
+  if reference.starts_with("//") {
+    return Some(format!("{}:{}", scheme, reference));
+  }
+
+  let (path, suffix) = split_path(reference);
+  let path = if path.is_empty() {
+    if base_path.is_empty() { String::from("/") } else { String::from(base_path) }
+  } else if path.starts_with('/') {
+    remove_dot_segments(path)
+  } else {
+    let dir = &base_path[..base_path.rfind('/').map_or(0, |v| v + 1)];
+    let dir = if dir.is_empty() { "/" } else { dir };
+    remove_dot_segments(&format!("{}{}", dir, path))
+  };
+
+  Some(format!("{}://{}{}{}", scheme, authority, path, suffix))
+}
+
+impl Url {
+  pub fn parse(s: &str, policy: &UrlPolicy) -> Result<Self, UrlError> {
+    // browsers ignore tabs and newlines anywhere in a URL, e.g. "java\nscript:"
+    let s: String = s.trim_matches(|c: char| c.is_ascii_control() || c == ' ').chars().filter(|c| !matches!(c, '\t' | '\n' | '\r')).collect();
+
+    if s.is_empty() {
+      return Err(UrlError::Empty);
+    }
+    if s.chars().any(|c| c.is_control()) {
+      return Err(UrlError::InvalidCharacter);
+    }
+
+    if let Some((scheme, rest)) = split_scheme(&s) {
+      if !policy.is_scheme_allowed(&scheme) {
+        return Err(UrlError::SchemeNotAllowed(scheme));
+      }
+      if rest.is_empty() {
+        return Err(UrlError::Empty);
+      }
+
+      return Ok(Self { href: format!("{}:{}", scheme, normalize_percent_encoding(rest)), scheme: Some(scheme) });
+    }
+
+    if !s.starts_with('#') && let Some(base) = &policy.base && let Some(resolved) = resolve(base, &s) {
+      return Self::parse(&resolved, &UrlPolicy { base: None, ..policy.clone() });
+    }
+
+    Ok(Self { href: normalize_percent_encoding(&s), scheme: None })
+  }
+
+  /// A link to an element in the same page.
+  pub fn fragment(id: &str) -> Self {
+    Self { href: format!("#{}", normalize_percent_encoding(id)), scheme: None }
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.href
+  }
+
+  pub fn scheme(&self) -> Option<&str> {
+    self.scheme.as_deref()
+  }
+
+  pub fn is_absolute(&self) -> bool {
+    self.scheme.is_some()
+  }
+
+  /// `scheme://host:port` of a URL with an authority, lowercased and with the default port filled in.
+  pub fn origin(&self) -> Option<String> {
+    let scheme = self.scheme.as_deref()?;
+    let rest = self.href[scheme.len()+1..].strip_prefix("//")?;
+    let authority = &rest[..rest.find(['/', '?', '#']).unwrap_or(rest.len())];
+    let host_port = authority.rsplit_once('@').map_or(authority, |(_, v)| v);
+
+    // [::1]:8080 のように host にも `:` が入ることがある
+    let (host, port) = match host_port.rsplit_once(':') {
+      Some((host, port)) if !port.contains(']') => (host, port),
+      _ => (host_port, ""),
+    };
+    let port = match (port, scheme) {
+      ("", "http") => "80",
+      ("", "https") => "443",
+      (port, _) => port,
+    };
+    Some(format!("{}://{}:{}", scheme, host.to_ascii_lowercase(), port))
+  }
+}
+
+impl From<Url> for String {
+  fn from(value: Url) -> String {
+    value.href
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(s: &str) -> Result<String, UrlError> {
+    Url::parse(s, &UrlPolicy::default()).map(String::from)
+  }
+
+  fn parse_with_base(s: &str) -> Result<String, UrlError> {
+    let policy = UrlPolicy { base: Some(String::from("https://example.com/dir/page?x=1")), ..UrlPolicy::default() };
+    Url::parse(s, &policy).map(String::from)
+  }
+
+  #[test]
+  fn test_absolute() {
+    assert_eq!(parse("https://example.com/a?b=c#d"), Ok(String::from("https://example.com/a?b=c#d")));
+    assert_eq!(parse("HTTPS://example.com"), Ok(String::from("https://example.com")));
+    assert_eq!(parse("mailto:someone@example.com"), Ok(String::from("mailto:someone@example.com")));
+  }
+
+  #[test]
+  fn test_disallowed_scheme() {
+    assert_eq!(parse("javascript:alert(1)"), Err(UrlError::SchemeNotAllowed(String::from("javascript"))));
+    assert_eq!(parse("JavaScript:alert(1)"), Err(UrlError::SchemeNotAllowed(String::from("javascript"))));
+    assert_eq!(parse(" java\nscript:alert(1)"), Err(UrlError::SchemeNotAllowed(String::from("javascript"))));
+    assert_eq!(parse("data:text/html,<script>"), Err(UrlError::SchemeNotAllowed(String::from("data"))));
+  }
+
+  #[test]
+  fn test_invalid() {
+    assert_eq!(parse(""), Err(UrlError::Empty));
+    assert_eq!(parse("  "), Err(UrlError::Empty));
+    assert_eq!(parse("https:"), Err(UrlError::Empty));
+    assert_eq!(parse("/a\u{7f}b"), Err(UrlError::InvalidCharacter));
+  }
+
+  #[test]
+  fn test_percent_encoding() {
+    assert_eq!(parse("https://example.com/a b"), Ok(String::from("https://example.com/a%20b")));
+    assert_eq!(parse("https://example.com/%e3%81%82"), Ok(String::from("https://example.com/%E3%81%82")));
+    assert_eq!(parse("https://example.com/あ"), Ok(String::from("https://example.com/%E3%81%82")));
+    assert_eq!(parse("https://example.com/100%"), Ok(String::from("https://example.com/100%25")));
+    assert_eq!(parse(r#"/"><script>"#), Ok(String::from("/%22%3E%3Cscript%3E")));
+  }
+
+  #[test]
+  fn test_origin() {
+    let origin = |s: &str| Url::parse(s, &UrlPolicy::default()).unwrap().origin();
+    assert_eq!(origin("https://Example.com/a?b#c"), Some(String::from("https://example.com:443")));
+    assert_eq!(origin("http://user@example.com:8080"), Some(String::from("http://example.com:8080")));
+    assert_eq!(origin("https://[::1]/"), Some(String::from("https://[::1]:443")));
+    assert_eq!(origin("mailto:a@example.com"), None);
+    assert_eq!(origin("/page"), None);
+  }
+
+  #[test]
+  fn test_relative_without_base() {
+    assert_eq!(parse("/page"), Ok(String::from("/page")));
+    assert_eq!(parse("#anchor"), Ok(String::from("#anchor")));
+  }
+
+  #[test]
+  fn test_relative_with_base() {
+    assert_eq!(parse_with_base("/page"), Ok(String::from("https://example.com/page")));
+    assert_eq!(parse_with_base("other"), Ok(String::from("https://example.com/dir/other")));
+    assert_eq!(parse_with_base("../up/./x"), Ok(String::from("https://example.com/up/x")));
+    assert_eq!(parse_with_base("?y=2"), Ok(String::from("https://example.com/dir/page?y=2")));
+    assert_eq!(parse_with_base("//cdn.example.com/a"), Ok(String::from("https://cdn.example.com/a")));
+    assert_eq!(parse_with_base("#anchor"), Ok(String::from("#anchor")));
+  }
+
+  #[test]
+  fn test_is_absolute_url() {
+    assert!(is_absolute_url("https://example.com"));
+    assert!(is_absolute_url("mailto:a@example.com"));
+    assert!(!is_absolute_url("/page"));
+    assert!(!is_absolute_url("https:"));
+    assert!(!is_absolute_url("1http://example.com"));
+  }
+
+  #[test]
+  fn test_bare_url_len() {
+    assert_eq!(bare_url_len("http://example.com/a b"), Some(20));
+    assert_eq!(bare_url_len("https://example.com."), Some(19));
+    assert_eq!(bare_url_len("(see https://example.com)"), None);
+    assert_eq!(bare_url_len("https://example.com)"), Some(19));
+    assert_eq!(bare_url_len("https://en.wikipedia.org/wiki/A_(b)"), Some(35));
+    assert_eq!(bare_url_len("mailto:a@example.com"), None);
+    assert_eq!(bare_url_len("http:// x"), None);
+    assert_eq!(bare_url_len("https://example.com/a_(b_(c)))."), Some(29));
+    assert_eq!(bare_url_len("https://example.com/a(b)?"), Some(24));
+  }
+}